@@ -0,0 +1,233 @@
+use crate::entry::Entry;
+use crate::import::RowError;
+use crate::query::Field;
+
+/// Keys of the address parts, joined in this order into the address.
+const ADDRESS_KEYS: [&str; 6] = ["address", "address2", "city", "state", "zip", "country"];
+
+/// Keys of phone numbers by preference; the first one set becomes the phone
+/// number.
+const PHONE_KEYS: [&str; 3] = ["phone", "mobile", "workphone"];
+
+/// Fields of a record as key and value.
+type Record = Vec<(String, String)>;
+
+/// Reads a record. The address parts, and the phone number if it has
+/// another key than `phone`, are also kept as custom fields by their keys,
+/// so that export can write them back the same way.
+fn record_entry(values: &[(String, String)]) -> Result<Entry, String> {
+    let mut e = Entry::default();
+    for (key, value) in values.iter().filter(|(_, v)| !v.trim().is_empty()) {
+        let value = value.trim();
+        match key.as_str() {
+            "name" => e.set(Field::Name, value),
+            "email" => {
+                // the first address is the e-mail, the others are kept as they are
+                let (first, rest) = value.split_once(',').unwrap_or((value, ""));
+                e.e_mail = first.trim().to_string();
+                if !rest.trim().is_empty() {
+                    e.custom
+                        .insert("email".to_string(), rest.trim().to_string());
+                }
+            }
+            "notes" => e.note = value.to_string(),
+            "groups" => e.tags = Entry::parse_tags(value),
+            "organization" => e.organization = value.to_string(),
+            "title" => e.title = value.to_string(),
+            "birthday" => e.birthday = value.to_string(),
+            _ => {
+                e.custom.insert(key.clone(), value.to_string());
+            }
+        }
+    }
+    if let Some(key) = PHONE_KEYS.iter().find(|k| e.custom.contains_key(**k)) {
+        e.phone = match *key {
+            "phone" => e.custom.remove(*key).unwrap_or_default(),
+            _ => e.custom[*key].clone(),
+        };
+    }
+    let address: Vec<&str> = ADDRESS_KEYS
+        .iter()
+        .filter_map(|k| e.custom.get(*k).map(|v| v.as_str()))
+        .collect();
+    e.address = address.join(", ");
+    // a plain address needs no parts
+    if e.custom
+        .keys()
+        .all(|k| k == "address" || !ADDRESS_KEYS.contains(&k.as_str()))
+    {
+        e.custom.remove("address");
+    }
+    if e.first_name.is_empty() {
+        return Err("record has no name".to_string());
+    }
+    Ok(e)
+}
+
+/// Reads an abook addressbook file. Fields that have no counterpart in an
+/// entry, such as `nick` or `url`, are kept as custom fields. Records that
+/// can not be read are reported with the line of their section header.
+pub fn read_entries(text: &str) -> (Vec<(usize, Entry)>, Vec<RowError>) {
+    let mut entries: Vec<(usize, Entry)> = Vec::new();
+    let mut errors: Vec<RowError> = Vec::new();
+    // records with the line of their header, None for the format section
+    let mut records: Vec<(usize, Option<Record>)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let values = match section.trim() {
+                "format" => None,
+                _ => Some(Vec::new()),
+            };
+            records.push((i + 1, values));
+            continue;
+        }
+        match (line.split_once('='), records.last_mut()) {
+            (Some((key, value)), Some((_, Some(values)))) => {
+                values.push((key.trim().to_lowercase(), value.to_string()));
+            }
+            (Some(_), Some((_, None))) => (),
+            _ => errors.push(RowError {
+                line: i + 1,
+                message: format!("{} is not a field of a record", line),
+            }),
+        }
+    }
+    for (line, values) in records.into_iter() {
+        if let Some(values) = values {
+            match record_entry(&values) {
+                Ok(e) => entries.push((line, e)),
+                Err(message) => errors.push(RowError { line, message }),
+            }
+        }
+    }
+    (entries, errors)
+}
+
+/// Writes entries as an abook addressbook file. Organization, title and
+/// birthday, which abook lacks, are written under those names along with the
+/// custom fields. Address parts and the key of the phone number that were
+/// read from abook are written back while the address and phone number are
+/// unchanged. Line breaks in values are replaced by spaces.
+pub fn write_entries(entries: &[Entry]) -> String {
+    let mut result =
+        "# abook addressbook file\n\n[format]\nprogram=phonebook\nversion=0.6.1\n".to_string();
+    for (i, e) in entries.iter().enumerate() {
+        let mut email = e.e_mail.clone();
+        if let Some(rest) = e.custom.get("email") {
+            if !email.is_empty() {
+                email.push(',');
+            }
+            email.push_str(rest);
+        }
+        let parts: Vec<&str> = ADDRESS_KEYS
+            .iter()
+            .filter_map(|k| e.custom.get(*k).map(|v| v.as_str()))
+            .collect();
+        let split = !parts.is_empty() && parts.join(", ") == e.address;
+        let address = match split {
+            true => e.custom.get("address").cloned().unwrap_or_default(),
+            false => e.address.clone(),
+        };
+        let phone = match PHONE_KEYS[1..]
+            .iter()
+            .any(|k| e.custom.get(*k) == Some(&e.phone))
+        {
+            true => String::new(),
+            false => e.phone.clone(),
+        };
+        let mut values: Vec<(&str, String)> = vec![
+            ("name", e.get(Field::Name)),
+            ("email", email),
+            ("address", address),
+            ("phone", phone),
+            ("notes", e.note.clone()),
+            ("groups", e.tags.join(",")),
+            ("organization", e.organization.clone()),
+            ("title", e.title.clone()),
+            ("birthday", e.birthday.clone()),
+        ];
+        for (key, value) in e.custom.iter() {
+            let stale = !split && ADDRESS_KEYS.contains(&key.as_str());
+            if !stale && !values.iter().any(|(k, _)| k == key) {
+                values.push((key.as_str(), value.clone()));
+            }
+        }
+        result.push_str(format!("\n[{}]\n", i).as_str());
+        for (key, value) in values.iter().filter(|(_, v)| !v.is_empty()) {
+            let value = value.replace("\r\n", " ").replace(['\n', '\r'], " ");
+            result.push_str(format!("{}={}\n", key, value).as_str());
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_and_write_back() {
+        let text = "# abook addressbook file\n\
+            \n\
+            [format]\n\
+            program=abook\n\
+            version=0.6.1\n\
+            \n\
+            [0]\n\
+            name=John Smith\n\
+            email=john@example.com,js@example.com\n\
+            address=Main street 1\n\
+            city=Springfield\n\
+            zip=12345\n\
+            phone=\n\
+            mobile=+1 555 123 4567\n\
+            workphone=+1 555 000 1111\n\
+            nick=johnny\n\
+            groups=friends,work\n\
+            \n\
+            [1]\n\
+            email=nobody@example.com\n";
+        let (entries, errors) = read_entries(text);
+        assert_eq!(1, entries.len());
+        let (line, e) = &entries[0];
+        assert_eq!(7, *line);
+        assert_eq!("John", e.first_name);
+        assert_eq!("Smith", e.last_name);
+        assert_eq!("john@example.com", e.e_mail);
+        assert_eq!("Main street 1, Springfield, 12345", e.address);
+        assert_eq!("+1 555 123 4567", e.phone);
+        assert_eq!(vec!["friends", "work"], e.tags);
+        assert_eq!(Some(&"johnny".to_string()), e.custom.get("nick"));
+        assert_eq!(
+            Some(&"+1 555 000 1111".to_string()),
+            e.custom.get("workphone")
+        );
+        assert_eq!(Some(&"js@example.com".to_string()), e.custom.get("email"));
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(vec!["line 19: record has no name"], messages);
+
+        let written = write_entries(std::slice::from_ref(e));
+        assert!(written.contains(
+            "\n[0]\nname=John Smith\nemail=john@example.com,js@example.com\naddress=Main street 1\n"
+        ));
+        assert!(written.contains("\ncity=Springfield\n"));
+        assert!(written.contains("\nmobile=+1 555 123 4567\n"));
+        assert!(written.contains("\nzip=12345\n"));
+        assert!(!written.contains("\nphone="));
+        let (read, errors) = read_entries(&written);
+        assert!(errors.is_empty());
+        assert_eq!(e.to_string(), read[0].1.to_string());
+
+        let mut moved = e.clone();
+        moved.address = "Elm street 2, Shelbyville".to_string();
+        moved.phone = "+1 555 765 4321".to_string();
+        let written = write_entries(&[moved]);
+        assert!(written.contains("\naddress=Elm street 2, Shelbyville\nphone=+1 555 765 4321\n"));
+        assert!(!written.contains("\ncity="));
+        assert!(!written.contains("\nzip="));
+    }
+}
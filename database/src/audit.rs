@@ -0,0 +1,64 @@
+use crate::time::DateTime;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+
+/// Line of the audit log.
+pub struct Record {
+    pub time: u64,
+    pub action: String,
+    pub id: u16,
+}
+
+/// Audit log of a phonebook, next to its file. It records erasures only,
+/// as a tombstone of time and entry ID without any personal data.
+pub fn path(db_path: &str) -> String {
+    format!("{}.audit", db_path)
+}
+
+/// Appends a record `time;action;id` to the audit log.
+pub fn record(db_path: &str, now: u64, action: &str, id: u16) -> Result<(), String> {
+    let line = format!(
+        "{};{};{}\n",
+        DateTime::from_timestamp(now).to_extended(),
+        action,
+        id
+    );
+    let path = path(db_path);
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path.as_str())
+        .and_then(|mut f| f.write_all(line.as_bytes()));
+    match written {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("failed to write audit log {}: {}", path, e)),
+    }
+}
+
+/// Reads the records of the audit log, none if there is no log yet.
+pub fn read(db_path: &str) -> Result<Vec<Record>, String> {
+    let path = path(db_path);
+    let content = match fs::read_to_string(path.as_str()) {
+        Ok(x) => x,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("failed to read audit log {}: {}", path, e)),
+    };
+    let mut records: Vec<Record> = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let parts: Vec<&str> = line.split(';').collect();
+        let time = parts.first().and_then(|t| DateTime::parse(t)?.timestamp());
+        match (
+            time,
+            parts.get(1),
+            parts.get(2).and_then(|x| x.parse::<u16>().ok()),
+        ) {
+            (Some(time), Some(action), Some(id)) if parts.len() == 3 => records.push(Record {
+                time,
+                action: action.to_string(),
+                id,
+            }),
+            _ => return Err(format!("line {} of audit log {} is damaged", i + 1, path)),
+        }
+    }
+    Ok(records)
+}
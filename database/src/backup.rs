@@ -0,0 +1,93 @@
+use crate::entry::Entry;
+use crate::meta::Metadata;
+use crate::storage::Storage;
+use sha2::{Digest, Sha256};
+
+/// Version of the archive format, written in its first line.
+const VERSION: &str = "1";
+
+fn checksum(body: &str) -> String {
+    format!("{:x}", Sha256::digest(body.as_bytes()))
+}
+
+/// Writes a backup archive: a header with the archive version, the time it
+/// was made, the number of entries and the SHA-256 checksum of the rest,
+/// followed by the metadata and entries in text storage format. The entries
+/// keep their IDs, timestamps and custom fields.
+pub fn write(meta: &Metadata, entries: &[Entry], created_at: u64) -> Result<String, String> {
+    let body = Storage::Text.dump(meta, entries)?;
+    let header = [
+        format!("#phonebook-backup={}", VERSION),
+        format!("#created_at={}", created_at),
+        format!("#entries={}", entries.len()),
+        format!("#sha256={}", checksum(&body)),
+    ];
+    Ok(format!("{}\n{}", header.join("\n"), body))
+}
+
+/// Reads a backup archive, verifying its checksum and number of entries.
+pub fn read(archive: &str) -> Result<(Metadata, Vec<Entry>), String> {
+    let mut rest = archive;
+    let mut header: Vec<(String, String)> = Vec::new();
+    for key in ["phonebook-backup", "created_at", "entries", "sha256"] {
+        let (line, r) = rest.split_once('\n').unwrap_or((rest, ""));
+        match Metadata::parse_line(line) {
+            Some((k, v)) if k == key => header.push((k, v)),
+            _ if header.is_empty() => return Err("not a phonebook backup".to_string()),
+            _ => return Err(format!("backup header has no {}", key)),
+        }
+        rest = r;
+    }
+    if header[0].1 != VERSION {
+        return Err(format!("unsupported backup version {}", header[0].1));
+    }
+    if checksum(rest) != header[3].1 {
+        return Err("backup is damaged: checksum does not match".to_string());
+    }
+    let (meta, entries) = Storage::Text.load(rest)?;
+    if entries.len().to_string() != header[2].1 {
+        return Err(format!(
+            "backup is damaged: expected {} entries, found {}",
+            header[2].1,
+            entries.len()
+        ));
+    }
+    Ok((meta, entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn write_and_read() {
+        let mut meta = Metadata::default();
+        meta.set("region", "US");
+        let entries = vec![Entry {
+            id: 7,
+            first_name: "John".to_string(),
+            last_name: "Smith".to_string(),
+            note: "First line\nsecond; line".to_string(),
+            created_at: 1000,
+            updated_at: 2000,
+            custom: BTreeMap::from([("nick".to_string(), "johnny".to_string())]),
+            ..Default::default()
+        }];
+        let archive = write(&meta, &entries, 3000).unwrap();
+        assert!(archive.starts_with("#phonebook-backup=1\n#created_at=3000\n#entries=1\n#sha256="));
+        let (read_meta, read_entries) = read(&archive).unwrap();
+        assert_eq!(meta, read_meta);
+        assert_eq!(entries[0].to_string(), read_entries[0].to_string());
+
+        let damaged = archive.replace("Smith", "Smyth");
+        assert_eq!(
+            Err("backup is damaged: checksum does not match".to_string()),
+            read(&damaged).map(|_| ())
+        );
+        assert_eq!(
+            Err("not a phonebook backup".to_string()),
+            read("1;John").map(|_| ())
+        );
+    }
+}
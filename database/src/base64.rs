@@ -0,0 +1,70 @@
+// Standard base64 alphabet with padding, as used by LDIF.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => result.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char),
+                false => result.push('='),
+            }
+        }
+    }
+    result
+}
+
+/// Decodes base64, ignoring whitespace.
+pub fn decode(s: &str) -> Result<Vec<u8>, String> {
+    let mut result: Vec<u8> = Vec::with_capacity(s.len() / 4 * 3);
+    let mut n: u32 = 0;
+    let mut bits = 0;
+    let mut padding = 0;
+    for c in s.chars().filter(|c| !c.is_whitespace()) {
+        if c == '=' {
+            padding += 1;
+            continue;
+        }
+        if padding > 0 {
+            return Err("invalid base64: data after padding".to_string());
+        }
+        let value = match ALPHABET.iter().position(|a| *a as char == c) {
+            Some(x) => x as u32,
+            None => return Err(format!("invalid base64 character {}", c)),
+        };
+        n = n << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((n >> bits & 0xFF) as u8);
+        }
+    }
+    if padding > 2 || bits >= 6 {
+        return Err("invalid base64 length".to_string());
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        assert_eq!("", encode(b""));
+        assert_eq!("Zg==", encode(b"f"));
+        assert_eq!("Zm8=", encode(b"fo"));
+        assert_eq!("Zm9v", encode(b"foo"));
+        assert_eq!("Wm/DqyDDmA==", encode("Zoë Ø".as_bytes()));
+        assert_eq!(Ok("Zoë Ø".as_bytes().to_vec()), decode("Wm/D qyDD\nmA=="));
+        assert_eq!(Ok(b"fo".to_vec()), decode("Zm8="));
+        assert!(decode("Zm9v!").is_err());
+        assert!(decode("Z").is_err());
+    }
+}
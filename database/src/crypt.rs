@@ -0,0 +1,309 @@
+#[cfg(feature = "encryption")]
+use crate::base64;
+use std::env;
+
+/// First line of an encrypted phonebook file.
+const HEADER: &str = "#phonebook-encrypted=1";
+
+/// scrypt cost as log2 of N, which takes 32 MiB of memory with r = 8.
+#[cfg(feature = "encryption")]
+const LOG_N: u8 = 15;
+
+/// Highest scrypt cost accepted from a file, taking 1 GiB with r = 8.
+#[cfg(feature = "encryption")]
+const MAX_LOG_N: u8 = 20;
+
+/// Secret an encrypted phonebook is locked with.
+#[derive(Clone)]
+pub enum Key {
+    /// Passphrase, stretched with scrypt.
+    Passphrase(String),
+    /// Path of a file of at least 32 bytes, whose SHA-256 hash is the key.
+    File(String),
+}
+
+impl Key {
+    /// Reads the key from the `PHONEBOOK_KEY_FILE` or else the
+    /// `PHONEBOOK_PASSPHRASE` environment variable.
+    pub fn from_env() -> Option<Key> {
+        match (
+            env::var("PHONEBOOK_KEY_FILE"),
+            env::var("PHONEBOOK_PASSPHRASE"),
+        ) {
+            (Ok(path), _) if !path.is_empty() => Some(Key::File(path)),
+            (_, Ok(passphrase)) if !passphrase.is_empty() => Some(Key::Passphrase(passphrase)),
+            _ => None,
+        }
+    }
+}
+
+/// Returns true if file content is an encrypted phonebook.
+pub fn is_encrypted(content: &str) -> bool {
+    content.starts_with(HEADER)
+}
+
+/// Secret of an open encrypted phonebook with the key derived from it and
+/// the key derivation header, so that saving only needs a new nonce.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+pub struct Cipher {
+    key: Key,
+    /// Value of the `#kdf` header, naming the key derivation and its salt.
+    kdf: String,
+    derived: [u8; 32],
+}
+
+impl Cipher {
+    /// Derives a key from the secret, with a new salt for a passphrase.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    pub fn new(key: Key) -> Result<Cipher, String> {
+        #[cfg(feature = "encryption")]
+        return Cipher::with_cost(key, LOG_N);
+        #[cfg(not(feature = "encryption"))]
+        Err("encrypted phonebooks need the encryption feature".to_string())
+    }
+
+    /// Decrypts the content of an encrypted phonebook file, returning it with
+    /// the cipher to encrypt it again.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    pub fn open(content: &str, key: Key) -> Result<(String, Cipher), String> {
+        #[cfg(feature = "encryption")]
+        return Cipher::with_header(content, key);
+        #[cfg(not(feature = "encryption"))]
+        Err("encrypted phonebooks need the encryption feature".to_string())
+    }
+
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    /// Encrypts the content of a phonebook file with XChaCha20-Poly1305. The
+    /// result is a header naming the key derivation, its salt and the nonce,
+    /// followed by the base64 encoded ciphertext; the header is authenticated.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    pub fn encrypt(&self, plain: &str) -> Result<String, String> {
+        #[cfg(feature = "encryption")]
+        return seal(plain, &self.kdf, &self.derived);
+        #[cfg(not(feature = "encryption"))]
+        Err("encrypted phonebooks need the encryption feature".to_string())
+    }
+
+    /// Decrypts the content of an encrypted phonebook file, such as a
+    /// snapshot. The derived key is reused if the file has the same salt.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    pub fn decrypt(&self, content: &str) -> Result<String, String> {
+        #[cfg(feature = "encryption")]
+        return match split(content)?.1 {
+            kdf if kdf == self.kdf => unseal(content, &self.derived),
+            kdf => unseal(content, &derive(&self.key, kdf)?),
+        };
+        #[cfg(not(feature = "encryption"))]
+        Err("encrypted phonebooks need the encryption feature".to_string())
+    }
+
+    #[cfg(feature = "encryption")]
+    fn with_header(content: &str, key: Key) -> Result<(String, Cipher), String> {
+        let kdf = split(content)?.1;
+        let cipher = Cipher {
+            derived: derive(&key, kdf)?,
+            kdf: kdf.to_string(),
+            key,
+        };
+        Ok((unseal(content, &cipher.derived)?, cipher))
+    }
+
+    #[cfg(feature = "encryption")]
+    fn with_cost(key: Key, log_n: u8) -> Result<Cipher, String> {
+        let kdf = match key {
+            Key::Passphrase(_) => format!(
+                "scrypt;log_n={};r=8;p=1;salt={}",
+                log_n,
+                base64::encode(&random(16)?)
+            ),
+            Key::File(_) => "keyfile".to_string(),
+        };
+        Ok(Cipher {
+            derived: derive(&key, &kdf)?,
+            kdf,
+            key,
+        })
+    }
+}
+
+#[cfg(feature = "encryption")]
+fn random(len: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0; len];
+    match getrandom::getrandom(&mut bytes) {
+        Ok(()) => Ok(bytes),
+        Err(e) => Err(format!("failed to get random bytes: {}", e)),
+    }
+}
+
+/// Derives the 32 byte key described by the `kdf` header value.
+#[cfg(feature = "encryption")]
+fn derive(key: &Key, kdf: &str) -> Result<[u8; 32], String> {
+    use sha2::{Digest, Sha256};
+    let mut result = [0u8; 32];
+    let params: Vec<(&str, &str)> = kdf
+        .split(';')
+        .skip(1)
+        .filter_map(|p| p.split_once('='))
+        .collect();
+    let param = |name: &str| params.iter().find(|(k, _)| *k == name).map(|(_, v)| *v);
+    match (kdf.split(';').next(), key) {
+        (Some("scrypt"), Key::Passphrase(passphrase)) => {
+            let number = |name: &str| param(name).and_then(|v| v.parse::<u32>().ok());
+            let log_n = param("log_n").and_then(|v| v.parse::<u8>().ok());
+            let (log_n, r, p) = match (log_n, number("r"), number("p")) {
+                (Some(log_n), Some(r), Some(p)) => (log_n, r, p),
+                _ => return Err("invalid scrypt parameters".to_string()),
+            };
+            if !(1..=MAX_LOG_N).contains(&log_n) {
+                return Err(format!("scrypt cost log_n={} is out of range", log_n));
+            }
+            let salt = base64::decode(param("salt").unwrap_or_default())?;
+            let params = match scrypt::Params::new(log_n, r, p, 32) {
+                Ok(x) => x,
+                Err(e) => return Err(format!("invalid scrypt parameters: {}", e)),
+            };
+            if let Err(e) = scrypt::scrypt(passphrase.as_bytes(), &salt, &params, &mut result) {
+                return Err(format!("failed to derive key: {}", e));
+            }
+        }
+        (Some("keyfile"), Key::File(path)) => {
+            let bytes = match std::fs::read(path) {
+                Ok(x) => x,
+                Err(e) => return Err(format!("failed to read key file {}: {}", path, e)),
+            };
+            if bytes.len() < 32 {
+                return Err("key file must hold at least 32 bytes".to_string());
+            }
+            result.copy_from_slice(&Sha256::digest(&bytes));
+        }
+        (Some("scrypt"), Key::File(_)) => {
+            return Err("phonebook is encrypted with a passphrase".to_string())
+        }
+        (Some("keyfile"), Key::Passphrase(_)) => {
+            return Err("phonebook is encrypted with a key file".to_string())
+        }
+        _ => return Err(format!("unknown key derivation {}", kdf)),
+    }
+    Ok(result)
+}
+
+#[cfg(feature = "encryption")]
+fn seal(plain: &str, kdf: &str, derived: &[u8; 32]) -> Result<String, String> {
+    use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+    let nonce = random(24)?;
+    let header = format!(
+        "{}\n#kdf={}\n#nonce={}\n",
+        HEADER,
+        kdf,
+        base64::encode(&nonce)
+    );
+    let cipher = XChaCha20Poly1305::new(derived.into());
+    let payload = Payload {
+        msg: plain.as_bytes(),
+        aad: header.as_bytes(),
+    };
+    let ciphertext = match cipher.encrypt(XNonce::from_slice(&nonce), payload) {
+        Ok(x) => x,
+        Err(_) => return Err("failed to encrypt phonebook".to_string()),
+    };
+    let encoded = base64::encode(&ciphertext);
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|c| std::str::from_utf8(c).unwrap_or_default())
+        .collect();
+    Ok(format!("{}{}\n", header, lines.join("\n")))
+}
+
+/// Splits an encrypted file into its authenticated header, the key
+/// derivation, the nonce and the ciphertext.
+#[cfg(feature = "encryption")]
+fn split(content: &str) -> Result<(String, &str, Vec<u8>, &str), String> {
+    let lines: Vec<&str> = content.splitn(4, '\n').collect();
+    let value = |i: usize, name: &str| {
+        lines
+            .get(i)
+            .and_then(|l| l.strip_prefix('#'))
+            .and_then(|l| l.strip_prefix(name))
+            .and_then(|l| l.strip_prefix('='))
+    };
+    let (kdf, nonce, body) = match (
+        lines.first(),
+        value(1, "kdf"),
+        value(2, "nonce"),
+        lines.get(3),
+    ) {
+        (Some(&HEADER), Some(kdf), Some(nonce), Some(body)) => (kdf, base64::decode(nonce)?, body),
+        _ => return Err("invalid header of encrypted phonebook".to_string()),
+    };
+    if nonce.len() != 24 {
+        return Err("invalid nonce of encrypted phonebook".to_string());
+    }
+    let header = format!("{}\n{}\n{}\n", lines[0], lines[1], lines[2]);
+    Ok((header, kdf, nonce, body))
+}
+
+#[cfg(feature = "encryption")]
+fn unseal(content: &str, derived: &[u8; 32]) -> Result<String, String> {
+    use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+    let (header, _, nonce, body) = split(content)?;
+    let cipher = XChaCha20Poly1305::new(derived.into());
+    let payload = Payload {
+        msg: &base64::decode(body)?,
+        aad: header.as_bytes(),
+    };
+    let plain = match cipher.decrypt(XNonce::from_slice(&nonce), payload) {
+        Ok(x) => x,
+        Err(_) => return Err("wrong passphrase or key, or the file is damaged".to_string()),
+    };
+    match String::from_utf8(plain) {
+        Ok(x) => Ok(x),
+        Err(_) => Err("decrypted phonebook is not UTF-8 text".to_string()),
+    }
+}
+
+#[cfg(all(test, feature = "encryption"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let key = Key::Passphrase("correct horse".to_string());
+        let cipher = Cipher::with_cost(key.clone(), 4).unwrap();
+        let content = cipher.encrypt("#region=US\n1;John;Smith;;;;0;0").unwrap();
+        assert!(is_encrypted(&content));
+        assert!(!content.contains("John"));
+        let (plain, opened) = Cipher::open(&content, key).unwrap();
+        assert_eq!("#region=US\n1;John;Smith;;;;0;0", plain);
+        // saving again keeps the salt but not the nonce
+        let again = opened.encrypt(&plain).unwrap();
+        assert_eq!(content.lines().nth(1), again.lines().nth(1));
+        assert_ne!(content.lines().nth(2), again.lines().nth(2));
+        assert_eq!(Ok(plain), cipher.decrypt(&again));
+
+        let wrong = Key::Passphrase("battery staple".to_string());
+        assert_eq!(
+            Err("wrong passphrase or key, or the file is damaged".to_string()),
+            Cipher::open(&content, wrong.clone()).map(|(p, _)| p)
+        );
+        // the header is authenticated
+        let tampered = content.replace("log_n=4", "log_n=5");
+        assert!(cipher.decrypt(&tampered).is_err());
+        let costly = content.replace("log_n=4", "log_n=40");
+        assert_eq!(
+            Err("scrypt cost log_n=40 is out of range".to_string()),
+            Cipher::open(&costly, wrong).map(|(p, _)| p)
+        );
+        let file = Key::File("/nonexistent".to_string());
+        assert_eq!(
+            Err("phonebook is encrypted with a passphrase".to_string()),
+            Cipher::open(&content, file).map(|(p, _)| p)
+        );
+    }
+}
@@ -0,0 +1,356 @@
+use crate::entry::Entry;
+use crate::import::RowError;
+use crate::query::Field;
+
+/// Record of a CSV file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Line the record starts on, counting from 1.
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+/// Delimiters recognized when none is given.
+const DELIMITERS: [char; 4] = [',', ';', '\t', '|'];
+
+/// Guesses the delimiter from the header line, picking the candidate
+/// occurring most often outside of quotes. Defaults to comma.
+pub fn detect_delimiter(text: &str) -> char {
+    let mut counts = [0usize; DELIMITERS.len()];
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '\n' if !quoted => break,
+            _ if !quoted => {
+                if let Some(i) = DELIMITERS.iter().position(|d| *d == c) {
+                    counts[i] += 1;
+                }
+            }
+            _ => (),
+        }
+    }
+    let mut best = 0;
+    for (i, count) in counts.iter().enumerate() {
+        if *count > counts[best] {
+            best = i;
+        }
+    }
+    DELIMITERS[best]
+}
+
+/// Splits text into records as described by RFC 4180. Fields may be quoted
+/// to contain delimiters, line breaks and doubled quotes. Both CRLF and LF
+/// end a record, and empty lines are skipped.
+///
+/// An unterminated quote is reported as an error of the record it starts
+/// in, ending the parse.
+pub fn parse(text: &str, delimiter: char) -> (Vec<Record>, Vec<RowError>) {
+    let mut records: Vec<Record> = Vec::new();
+    let mut errors: Vec<RowError> = Vec::new();
+    let mut fields: Vec<String> = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                _ => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            // a quote only starts quoting at the beginning of a field,
+            // elsewhere it is taken as is
+            '"' if field.is_empty() => quoted = true,
+            '\r' if chars.peek() == Some(&'\n') => (),
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                if !(fields.len() == 1 && fields[0].is_empty()) {
+                    records.push(Record {
+                        line: start,
+                        fields: std::mem::take(&mut fields),
+                    });
+                }
+                fields.clear();
+                line += 1;
+                start = line;
+            }
+            _ if c == delimiter => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        errors.push(RowError {
+            line: start,
+            message: "quoted field is not terminated".to_string(),
+        });
+        return (records, errors);
+    }
+    fields.push(field);
+    if !(fields.len() == 1 && fields[0].is_empty()) {
+        records.push(Record {
+            line: start,
+            fields,
+        });
+    }
+    (records, errors)
+}
+
+/// Formats one record, quoting fields where needed. The record ends with
+/// CRLF as required by RFC 4180.
+pub fn write_record(fields: &[String], delimiter: char) -> String {
+    let quoted: Vec<String> = fields
+        .iter()
+        .map(|f| {
+            let needs_quotes = f.contains(delimiter)
+                || f.contains('"')
+                || f.contains('\n')
+                || f.contains('\r')
+                || f.starts_with(' ')
+                || f.ends_with(' ');
+            match needs_quotes {
+                true => format!("\"{}\"", f.replace('"', "\"\"")),
+                false => f.clone(),
+            }
+        })
+        .collect();
+    format!("{}\r\n", quoted.join(delimiter.to_string().as_str()))
+}
+
+/// How to read a CSV file into entries.
+#[derive(Clone, Default)]
+pub struct CsvOptions {
+    /// Delimiter of fields, detected from the header if not given.
+    pub delimiter: Option<char>,
+    /// Fields of columns, by header name. Columns not listed are mapped by
+    /// their name, e.g. "First Name", "Surname" or "E-mail Address".
+    pub mapping: Vec<(String, Field)>,
+}
+
+/// Entries read from a CSV file.
+pub struct CsvImport {
+    /// Entries with the line their record starts on.
+    pub entries: Vec<(usize, Entry)>,
+    pub errors: Vec<RowError>,
+    /// Header names of columns not mapped to any field.
+    pub ignored: Vec<String>,
+}
+
+/// Parses a column mapping given as `column=field` pairs separated by
+/// commas, e.g. `Given Name=first,Mobile=phone`.
+pub fn parse_mapping(s: &str) -> Result<Vec<(String, Field)>, String> {
+    let mut mapping: Vec<(String, Field)> = Vec::new();
+    for part in s.split(',').filter(|p| !p.trim().is_empty()) {
+        let (column, field) = match part.rsplit_once('=') {
+            Some(x) => x,
+            None => return Err(format!("mapping {} must be given as column=field", part)),
+        };
+        let field = Field::from(field.trim())?;
+        if field == Field::Any {
+            return Err(format!("column {} can not be mapped to any field", column));
+        }
+        mapping.push((column.trim().to_string(), field));
+    }
+    Ok(mapping)
+}
+
+/// Returns the field a column is mapped to by its header name.
+fn column_field(name: &str) -> Option<Field> {
+    let key: String = name
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect();
+    match key.as_str() {
+        "id" => Some(Field::Id),
+        "name" | "fullname" | "displayname" => Some(Field::Name),
+        "first" | "firstname" | "givenname" | "forename" => Some(Field::FirstName),
+        "last" | "lastname" | "surname" | "familyname" => Some(Field::LastName),
+        "phone" | "phonenumber" | "telephone" | "tel" | "mobile" | "mobilephone" => {
+            Some(Field::Phone)
+        }
+        "address" | "postaladdress" | "homeaddress" => Some(Field::Address),
+        "email" | "mail" | "emailaddress" => Some(Field::Email),
+        "tag" | "tags" | "groups" | "categories" => Some(Field::Tag),
+        "org" | "organization" | "company" => Some(Field::Organization),
+        "note" | "notes" => Some(Field::Note),
+        "birthday" | "bday" | "dateofbirth" => Some(Field::Birthday),
+        "title" | "jobtitle" => Some(Field::Title),
+        _ => None,
+    }
+}
+
+/// Reads entries from CSV text whose first record is a header. Records that
+/// can not be read are reported without stopping the import; the result is
+/// an error only if the header can not be used.
+pub fn read_entries(text: &str, options: &CsvOptions) -> Result<CsvImport, String> {
+    let delimiter = options.delimiter.unwrap_or_else(|| detect_delimiter(text));
+    let (records, mut errors) = parse(text, delimiter);
+    let mut records = records.into_iter();
+    let header = match records.next() {
+        Some(x) => x.fields,
+        None => return Err("file has no header".to_string()),
+    };
+    for (column, _) in options.mapping.iter() {
+        if !header.iter().any(|h| h.trim().eq_ignore_ascii_case(column)) {
+            return Err(format!("column {} is not in the header", column));
+        }
+    }
+    let mut ignored: Vec<String> = Vec::new();
+    let columns: Vec<Option<Field>> = header
+        .iter()
+        .map(|h| {
+            let field = match options
+                .mapping
+                .iter()
+                .find(|(c, _)| h.trim().eq_ignore_ascii_case(c))
+            {
+                Some((_, f)) => Some(*f),
+                None => column_field(h),
+            };
+            if field.is_none() {
+                ignored.push(h.clone());
+            }
+            field
+        })
+        .collect();
+    if !columns
+        .iter()
+        .any(|f| matches!(f, Some(f) if *f != Field::Id))
+    {
+        return Err("no column of the header could be mapped to a field".to_string());
+    }
+    let mut entries: Vec<(usize, Entry)> = Vec::new();
+    for record in records {
+        if record.fields.len() != header.len() {
+            errors.push(RowError {
+                line: record.line,
+                message: format!(
+                    "expected {} fields, found {}",
+                    header.len(),
+                    record.fields.len()
+                ),
+            });
+            continue;
+        }
+        let mut e = Entry::default();
+        for (field, value) in columns.iter().zip(record.fields.iter()) {
+            // with several columns of one field the first value is kept
+            if let Some(field) = field {
+                if !value.trim().is_empty() && e.get(*field).is_empty() {
+                    e.set(*field, value);
+                }
+            }
+        }
+        entries.push((record.line, e));
+    }
+    errors.sort_by_key(|e| e.line);
+    Ok(CsvImport {
+        entries,
+        errors,
+        ignored,
+    })
+}
+
+/// Writes entries as CSV with a header of field names.
+pub fn write_entries(entries: &[Entry], columns: &[Field], delimiter: char) -> String {
+    let header: Vec<String> = columns.iter().map(|f| f.key().to_string()).collect();
+    let mut result = write_record(&header, delimiter);
+    for e in entries.iter() {
+        let fields: Vec<String> = columns.iter().map(|f| e.get(*f)).collect();
+        result.push_str(write_record(&fields, delimiter).as_str());
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_quoted_fields() {
+        let text = "a,\"b,c\",\"say \"\"hi\"\"\"\r\n\r\n1,\"two\nlines\",3\n4,5,6";
+        let (records, errors) = parse(text, ',');
+        assert!(errors.is_empty());
+        assert_eq!(3, records.len());
+        assert_eq!(vec!["a", "b,c", "say \"hi\""], records[0].fields);
+        assert_eq!(vec!["1", "two\nlines", "3"], records[1].fields);
+        assert_eq!(3, records[1].line);
+        assert_eq!(5, records[2].line);
+        let (records, errors) = parse("a,b\n\"open,c\nd", ',');
+        assert_eq!(1, records.len());
+        assert_eq!(2, errors[0].line);
+    }
+
+    #[test]
+    fn detect_delimiters() {
+        assert_eq!(';', detect_delimiter("first;last;\"a,b,c\"\n1,2,3"));
+        assert_eq!('\t', detect_delimiter("first\tlast\n"));
+        assert_eq!(',', detect_delimiter("first"));
+    }
+
+    #[test]
+    fn read_with_mapping_and_errors() {
+        let text =
+            "Given Name;Surname;Mobile;Nickname\nJohn;Smith;+1 555 123 4567;Johnny\nMary;Jones\n";
+        let options = CsvOptions {
+            mapping: parse_mapping("Mobile=phone").unwrap(),
+            ..Default::default()
+        };
+        let import = read_entries(text, &options).unwrap();
+        assert_eq!(1, import.entries.len());
+        let (line, e) = &import.entries[0];
+        assert_eq!(2, *line);
+        assert_eq!("John", e.first_name);
+        assert_eq!("Smith", e.last_name);
+        assert_eq!("+1 555 123 4567", e.phone);
+        assert_eq!(vec!["Nickname"], import.ignored);
+        assert_eq!(
+            "line 3: expected 4 fields, found 2",
+            import.errors[0].to_string()
+        );
+        assert!(read_entries("Foo,Bar\n1,2", &CsvOptions::default()).is_err());
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let e = Entry {
+            id: 1,
+            first_name: "John".to_string(),
+            last_name: "Smith".to_string(),
+            address: "Main street 1, \"Old town\"".to_string(),
+            tags: vec!["work".to_string(), "friends".to_string()],
+            ..Default::default()
+        };
+        let columns = [
+            Field::Id,
+            Field::FirstName,
+            Field::LastName,
+            Field::Address,
+            Field::Tag,
+        ];
+        let text = write_entries(&[e], &columns, ',');
+        assert_eq!(
+            "id,first,last,address,tag\r\n1,John,Smith,\"Main street 1, \"\"Old town\"\"\",\"work,friends\"\r\n",
+            text
+        );
+        let import = read_entries(&text, &CsvOptions::default()).unwrap();
+        let read = &import.entries[0].1;
+        assert_eq!("Main street 1, \"Old town\"", read.address);
+        assert_eq!(vec!["work", "friends"], read.tags);
+        assert_eq!(0, read.id);
+    }
+}
@@ -1,23 +1,126 @@
+use crate::audit;
+use crate::backup;
+use crate::crypt::{self, Cipher, Key};
+use crate::duplicates::{self, Duplicate};
 use crate::entry::Entry;
-use std::fs;
+use crate::fuzzy::{self, Match};
+use crate::meta::Metadata;
+use crate::normalize::MatchOptions;
+use crate::phone::{PhoneDigits, PhoneMatch, PhoneNumber, Region};
+use crate::phonetic::{self, PhoneticIndex};
+use crate::query::Query;
+use crate::redact::Redaction;
+use crate::snapshot::{self, Policy, Snapshot};
+use crate::storage::{Storage, FORMAT_KEY, FORMAT_VERSION};
+use crate::validation::Rules;
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
 use std::time::SystemTime;
 
 pub trait DB {
     fn create(&mut self, e: Entry) -> Result<Entry, String>;
+    /// Creates an entry like `create` but only in memory, so that many
+    /// entries can be written with one `flush`. An update time set on the
+    /// entry, e.g. the REV of an imported vCard, is kept.
+    fn insert(&mut self, e: Entry) -> Result<Entry, String>;
+    /// Writes the entries created with `insert`.
+    fn flush(&self) -> Result<(), String>;
     fn update(&mut self, id: u16, e: Entry) -> Result<Entry, String>;
     fn delete(&mut self, id: u16) -> Result<Entry, String>;
     fn read_all(&self) -> Vec<Entry>;
     fn read_by_id(&self, id: u16) -> Option<Entry>;
-    fn search(&self, term: String) -> Vec<Entry>;
+    fn search(&self, query: &Query) -> Vec<Entry>;
+    fn fuzzy_search(&self, term: &str, options: MatchOptions) -> Vec<Match>;
+    fn phonetic_search(&self, term: &str) -> Vec<Entry>;
+    fn lookup(&self, number: &str) -> Vec<Entry>;
+    fn duplicates(&self, min_confidence: u8) -> Vec<Duplicate>;
+    fn merge(&mut self, keep: u16, other: u16) -> Result<Entry, String>;
+    fn find_existing(&self, e: &Entry) -> Vec<Entry>;
+    /// Validates an entry the way `create` does, without storing it.
+    fn check(&self, e: &Entry) -> Result<(), String>;
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// What is stored about the person of an entry.
+pub struct Subject {
+    pub id: u16,
+    /// Creation time of the entry, which tells it apart from deleted entries
+    /// that had the same ID.
+    pub created_at: u64,
+    /// The entry, None if it was deleted.
+    pub entry: Option<Entry>,
+    /// Copies of the entry in snapshots, by snapshot name.
+    pub snapshots: Vec<(String, Entry)>,
+    /// Records of the audit log about the entry.
+    pub audit: Vec<audit::Record>,
 }
 
 pub struct FileDB {
     path: String,
+    storage: Storage,
     entries: Vec<Entry>,
+    phonetic: PhoneticIndex,
+    region: Option<&'static Region>,
+    meta: Metadata,
+    rules: Rules,
+    redaction: Redaction,
+    snapshots: Option<Policy>,
+    /// Whether the file was copied to a snapshot since it was opened.
+    snapshot_taken: Cell<bool>,
+    /// Key of an encrypted file, derived once when it is opened, None if it
+    /// is stored in plain text.
+    key: Option<Cipher>,
+}
+
+/// Decrypts file content if it is encrypted.
+fn decrypt(content: String, key: Option<&Cipher>) -> Result<String, String> {
+    match (crypt::is_encrypted(content.as_str()), key) {
+        (false, _) => Ok(content),
+        (true, Some(k)) => k.decrypt(content.as_str()),
+        (true, None) => Err("DB is encrypted, a passphrase or key file is needed".to_string()),
+    }
+}
+
+/// Replaces a file by writing a temporary file next to it and renaming it,
+/// so that it is never left half written.
+fn replace_file(path: &str, content: &str) -> std::io::Result<()> {
+    let tmp = format!("{}.tmp", path);
+    let written = fs::File::create(tmp.as_str())
+        .and_then(|mut f| f.write_all(content.as_bytes()).and_then(|_| f.sync_all()))
+        .and_then(|_| fs::rename(tmp.as_str(), path));
+    if written.is_err() {
+        let _ = fs::remove_file(tmp.as_str());
+    }
+    written
 }
 
 impl FileDB {
+    /// Opens a database file, stored as JSON Lines if its name ends with
+    /// `.jsonl` and as text otherwise. An encrypted file is decrypted with the
+    /// key from the environment, see `Key::from_env`.
     pub fn new(path: String) -> Result<FileDB, String> {
+        FileDB::open(path, Key::from_env())
+    }
+
+    /// Opens a database file, decrypting it with `key` if it is encrypted.
+    pub fn open(path: String, key: Option<Key>) -> Result<FileDB, String> {
+        let storage = Storage::for_path(path.as_str())?;
+        FileDB::load(path, storage, key)
+    }
+
+    pub fn with_storage(path: String, storage: Storage) -> Result<FileDB, String> {
+        FileDB::load(path, storage, Key::from_env())
+    }
+
+    fn load(path: String, storage: Storage, key: Option<Key>) -> Result<FileDB, String> {
         let content: String = match fs::read_to_string(path.as_str()) {
             Ok(s) => s,
             Err(e) => {
@@ -26,23 +129,393 @@ impl FileDB {
                 return Err(err);
             }
         };
-        let entries: Vec<Entry> = content
-            .split('\n')
-            .map(|s| Entry::from(s))
-            .filter(|r| r.is_ok())
-            .map(|r| r.unwrap())
+        // the key is only kept for files that are encrypted already
+        let opened = match (crypt::is_encrypted(content.as_str()), key) {
+            (true, Some(k)) => Cipher::open(content.as_str(), k).map(|(c, k)| (c, Some(k))),
+            _ => decrypt(content, None).map(|c| (c, None)),
+        };
+        let (content, key) = match opened {
+            Ok(x) => x,
+            Err(e) => return Err(format!("failed to load DB: {}", e)),
+        };
+        let (meta, entries) = match storage.load(content.as_str()) {
+            Ok(x) => x,
+            Err(e) => return Err(format!("failed to load DB: {}", e)),
+        };
+        FileDB::build(path, storage, key, meta, entries)
+    }
+
+    fn build(
+        path: String,
+        storage: Storage,
+        key: Option<Cipher>,
+        meta: Metadata,
+        entries: Vec<Entry>,
+    ) -> Result<FileDB, String> {
+        // versions differ in the text layout only, which JSON Lines lacks
+        match meta.get(FORMAT_KEY) {
+            Some(FORMAT_VERSION) | Some("1") | None => (),
+            Some(v) => return Err(format!("unsupported phonebook format {}", v)),
+        }
+        let rules = match Rules::from_metadata(&meta) {
+            Ok(x) => x,
+            Err(e) => return Err(format!("invalid rules in DB header: {}", e)),
+        };
+        let redaction = match Redaction::from_metadata(&meta) {
+            Ok(x) => x,
+            Err(e) => return Err(format!("invalid redaction in DB header: {}", e)),
+        };
+        let snapshots = match Policy::from_metadata(&meta) {
+            Ok(x) => x,
+            Err(e) => return Err(format!("invalid snapshot settings in DB header: {}", e)),
+        };
+        let phonetic = PhoneticIndex::build(&entries);
+        let mut db = FileDB {
+            path,
+            storage,
+            entries,
+            phonetic,
+            region: None,
+            meta,
+            rules,
+            redaction,
+            snapshots,
+            snapshot_taken: Cell::new(false),
+            key,
+        };
+        if let Some(code) = db.meta.get("region").map(|s| s.to_string()) {
+            db.set_region(code.as_str())?;
+        }
+        Ok(db)
+    }
+
+    /// Creates a new, empty phonebook at `path` with a format header followed
+    /// by the given metadata, e.g. its name, region and rules. Fails if the
+    /// file exists.
+    pub fn init(path: String, meta: &Metadata) -> Result<FileDB, String> {
+        let mut header = Metadata::default();
+        header.set(FORMAT_KEY, FORMAT_VERSION);
+        for (key, value) in meta.iter().filter(|(k, _)| *k != FORMAT_KEY) {
+            header.set(key, value);
+        }
+        let storage = Storage::for_path(path.as_str())?;
+        let db = FileDB::build(path, storage, None, header, Vec::new())?;
+        let content = db.dump()?;
+        let created = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(db.path.as_str())
+            .and_then(|mut f| f.write_all(content.as_bytes()));
+        match created {
+            Ok(()) => Ok(db),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                Err(format!("{} already exists", db.path))
+            }
+            Err(e) => Err(format!("failed to write {}: {}", db.path, e)),
+        }
+    }
+
+    /// Sets the region used to interpret phone numbers written without
+    /// country code. The phonebook's own region is read from the `region`
+    /// key of its metadata.
+    pub fn set_region(&mut self, code: &str) -> Result<(), String> {
+        match Region::find(code) {
+            Some(r) => {
+                self.region = Some(r);
+                Ok(())
+            }
+            None => Err(format!("unknown region {}", code)),
+        }
+    }
+
+    /// Returns true if a region was set, by the metadata or `set_region`.
+    pub fn has_region(&self) -> bool {
+        self.region.is_some()
+    }
+
+    /// Writes a backup archive of all entries and the metadata, encrypted
+    /// with the key of an encrypted phonebook.
+    pub fn backup(&self) -> Result<String, String> {
+        let archive = backup::write(&self.meta, &self.entries, now())?;
+        match &self.key {
+            Some(k) => k.encrypt(archive.as_str()),
+            None => Ok(archive),
+        }
+    }
+
+    /// Recreates a phonebook at `path` from a backup archive, in the storage
+    /// format of the path. A file that is not empty is only overwritten with
+    /// `force`, after taking a snapshot of it if its metadata asks for them.
+    /// An encrypted archive is opened with `key` and restored encrypted.
+    pub fn restore(
+        path: String,
+        archive: &str,
+        force: bool,
+        key: Option<Key>,
+    ) -> Result<FileDB, String> {
+        let opened = match (crypt::is_encrypted(archive), key.clone()) {
+            (true, Some(k)) => Cipher::open(archive, k).map(|(c, k)| (c, Some(k))),
+            (true, None) => {
+                Err("backup is encrypted, a passphrase or key file is needed".to_string())
+            }
+            (false, _) => Ok((archive.to_string(), None)),
+        };
+        let (archive, cipher) = match opened {
+            Ok(x) => x,
+            Err(e) => return Err(format!("failed to read backup: {}", e)),
+        };
+        let (meta, entries) = backup::read(archive.as_str())?;
+        if FileDB::has_content(path.as_str()) {
+            if !force {
+                return Err(format!("{} is not empty", path));
+            }
+            // a file that cannot be opened is kept too, its policy is unknown
+            let now = now();
+            match FileDB::open(path.clone(), key.clone()) {
+                Ok(existing) => {
+                    if let Some(policy) = existing.snapshots {
+                        snapshot::take(path.as_str(), now)?;
+                        snapshot::prune(path.as_str(), policy, now)?;
+                    }
+                }
+                Err(_) => {
+                    snapshot::take(path.as_str(), now)?;
+                }
+            }
+        }
+        let mut content = Storage::for_path(path.as_str())?.dump(&meta, &entries)?;
+        if let Some(k) = &cipher {
+            content = k.encrypt(content.as_str())?;
+        }
+        if let Err(e) = replace_file(path.as_str(), content.as_str()) {
+            return Err(format!("failed to write {}: {}", path, e));
+        }
+        FileDB::open(path, key)
+    }
+
+    /// Returns true if the file at `path` exists and is not empty, so that
+    /// `restore` would only overwrite it with `force`. A file that cannot be
+    /// inspected counts as not empty.
+    pub fn has_content(path: &str) -> bool {
+        match fs::metadata(path) {
+            Ok(m) => m.len() > 0,
+            Err(e) => e.kind() != ErrorKind::NotFound,
+        }
+    }
+
+    /// Lists the snapshots of the phonebook, oldest first.
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>, String> {
+        snapshot::list(self.path.as_str())
+    }
+
+    /// Replaces the phonebook with a snapshot, after taking a snapshot of it
+    /// so that this can be undone.
+    pub fn restore_snapshot(&mut self, name: &str) -> Result<(), String> {
+        let content = snapshot::read(self.path.as_str(), name)?;
+        let loaded =
+            decrypt(content.clone(), self.key.as_ref()).and_then(|c| self.storage.load(c.as_str()));
+        if let Err(e) = loaded {
+            return Err(format!("failed to load snapshot {}: {}", name, e));
+        }
+        let now = now();
+        snapshot::take(self.path.as_str(), now)?;
+        if let Some(policy) = self.snapshots {
+            snapshot::prune(self.path.as_str(), policy, now)?;
+        }
+        if let Err(e) = replace_file(self.path.as_str(), content.as_str()) {
+            return Err(format!("failed to save DB: {}", e));
+        }
+        let key = self.key.as_ref().map(|k| k.key().clone());
+        *self = FileDB::load(self.path.clone(), self.storage, key)?;
+        Ok(())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Encrypts the file and its snapshots with a new key, or stores them in
+    /// plain text if the key is None. No snapshot is taken of the file as it
+    /// was. Returns the snapshots that could not be read with the old key,
+    /// which are left as they were.
+    pub fn set_key(&mut self, key: Option<Key>) -> Result<Vec<String>, String> {
+        let cipher = match key {
+            Some(k) => Some(Cipher::new(k)?),
+            None => None,
+        };
+        let mut snapshots: Vec<(String, String)> = Vec::new();
+        let mut unreadable: Vec<String> = Vec::new();
+        for s in self.snapshots()? {
+            let content = snapshot::read(self.path.as_str(), s.name.as_str())?;
+            match decrypt(content, self.key.as_ref()) {
+                Ok(x) => snapshots.push((s.name, x)),
+                Err(_) => unreadable.push(s.name),
+            }
+        }
+        self.key = cipher;
+        self.snapshot_taken.set(true);
+        self.save()?;
+        for (name, content) in snapshots {
+            let content = match &self.key {
+                Some(k) => k.encrypt(content.as_str())?,
+                None => content,
+            };
+            snapshot::write(self.path.as_str(), name.as_str(), content.as_str())?;
+        }
+        Ok(unreadable)
+    }
+
+    /// Formats the phonebook as stored, but never encrypted.
+    pub fn dump(&self) -> Result<String, String> {
+        self.storage.dump(&self.meta, &self.entries)
+    }
+
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Collects what is stored about the person of an entry.
+    pub fn subject(&self, id: u16) -> Result<Subject, String> {
+        let snapshots = self.snapshot_entries()?;
+        let entry = self.read_by_id(id);
+        // IDs of deleted entries are given out again, so copies are matched
+        // by creation time too; a deleted entry is the latest one with the ID
+        let latest = snapshots
+            .iter()
+            .rev()
+            .find_map(|(_, entries)| entries.iter().find(|e| e.id == id));
+        let created_at = match (&entry, latest) {
+            (Some(e), _) | (None, Some(e)) => e.created_at,
+            (None, None) => return Err(format!("could not find entry with ID {}", id)),
+        };
+        let mut copies: Vec<(String, Entry)> = Vec::new();
+        for (name, entries) in snapshots {
+            let found = entries
+                .into_iter()
+                .find(|e| e.id == id && e.created_at == created_at);
+            if let Some(e) = found {
+                copies.push((name, e));
+            }
+        }
+        let records = audit::read(self.path.as_str())?
+            .into_iter()
+            .filter(|r| r.id == id && r.time >= created_at)
             .collect();
-        Ok(FileDB { path, entries })
+        Ok(Subject {
+            id,
+            created_at,
+            entry,
+            snapshots: copies,
+            audit: records,
+        })
+    }
+
+    /// Removes the entry of a person from the phonebook and its snapshots
+    /// without taking a new snapshot, and records a tombstone in the audit
+    /// log. Returns the number of snapshots the entry was removed from.
+    pub fn erase(&mut self, id: u16) -> Result<usize, String> {
+        let subject = self.subject(id)?;
+        if subject.entry.is_some() {
+            self.entries.retain(|e| e.id != id);
+            self.phonetic.remove(id);
+            self.snapshot_taken.set(true);
+            self.save()?;
+        }
+        for (name, entries) in self.snapshot_entries()? {
+            if !subject.snapshots.iter().any(|(n, _)| *n == name) {
+                continue;
+            }
+            let content = snapshot::read(self.path.as_str(), name.as_str())?;
+            let encrypted = crypt::is_encrypted(content.as_str());
+            let (meta, _) = self
+                .storage
+                .load(decrypt(content, self.key.as_ref())?.as_str())?;
+            let kept: Vec<Entry> = entries
+                .into_iter()
+                .filter(|e| e.id != id || e.created_at != subject.created_at)
+                .collect();
+            let mut content = self.storage.dump(&meta, &kept)?;
+            if let (true, Some(key)) = (encrypted, &self.key) {
+                content = key.encrypt(content.as_str())?;
+            }
+            snapshot::write(self.path.as_str(), name.as_str(), content.as_str())?;
+        }
+        audit::record(self.path.as_str(), now(), "erased", id)?;
+        Ok(subject.snapshots.len())
+    }
+
+    /// Reads the entries of all snapshots.
+    fn snapshot_entries(&self) -> Result<Vec<(String, Vec<Entry>)>, String> {
+        let mut result: Vec<(String, Vec<Entry>)> = Vec::new();
+        for s in self.snapshots()? {
+            let content = snapshot::read(self.path.as_str(), s.name.as_str())?;
+            let loaded =
+                decrypt(content, self.key.as_ref()).and_then(|c| self.storage.load(c.as_str()));
+            match loaded {
+                Ok((_, entries)) => result.push((s.name, entries)),
+                Err(e) => return Err(format!("failed to read snapshot {}: {}", s.name, e)),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Redaction policy of the phonebook, read from its metadata.
+    pub fn redaction(&self) -> &Redaction {
+        &self.redaction
+    }
+
+    /// Replaces the validation rules of the phonebook and saves them.
+    pub fn set_rules(&mut self, rules: Rules) -> Result<(), String> {
+        rules.to_metadata(&mut self.meta);
+        self.rules = rules;
+        self.save()
+    }
+
+    /// Checks an entry against the rules of the phonebook, including
+    /// uniqueness against all other entries, and fills in the canonical form
+    /// of its phone number. Entries with an ID in `ignore` are not compared.
+    fn validate(&self, e: &mut Entry, ignore: &[u16]) -> Result<(), String> {
+        if let Err(v) = self.rules.validate(e, self.region) {
+            return Err(format!("invalid entry: {}", v));
+        }
+        e.phone_e164 = self.canonical_phone(e.phone.as_str());
+        let others = self.entries.iter().filter(|x| !ignore.contains(&x.id));
+        if let Err(c) = self.rules.check_unique(e, others) {
+            return Err(format!("conflict: {}", c));
+        }
+        Ok(())
+    }
+
+    /// Returns the E.164 form of a phone number, or an empty string if it
+    /// can not be determined.
+    fn canonical_phone(&self, phone: &str) -> String {
+        match PhoneNumber::parse(phone, self.region) {
+            Ok(Some(number)) => number.e164(),
+            _ => "".to_string(),
+        }
+    }
+
+    /// Copies the file to a snapshot before it is first changed, if the
+    /// phonebook takes snapshots, and removes those beyond the policy.
+    fn snapshot(&self) -> Result<(), String> {
+        let policy = match (self.snapshots, self.snapshot_taken.get()) {
+            (Some(p), false) => p,
+            _ => return Ok(()),
+        };
+        let now = now();
+        snapshot::take(self.path.as_str(), now)?;
+        snapshot::prune(self.path.as_str(), policy, now)?;
+        self.snapshot_taken.set(true);
+        Ok(())
     }
 
     fn save(&self) -> Result<(), String> {
-        let content: String = self
-            .entries
-            .iter()
-            .map(|e| e.to_string())
-            .collect::<Vec<String>>()
-            .join("\n");
-        match fs::write(self.path.as_str(), content) {
+        self.snapshot()?;
+        let mut content = self.dump()?;
+        if let Some(key) = &self.key {
+            content = key.encrypt(content.as_str())?;
+        }
+        match replace_file(self.path.as_str(), content.as_str()) {
             Ok(()) => Ok(()),
             Err(e) => {
                 let mut err = "failed to save DB: ".to_string();
@@ -54,37 +527,46 @@ impl FileDB {
 }
 
 impl DB for FileDB {
-    fn create(&mut self, mut e: Entry) -> Result<Entry, String> {
+    fn create(&mut self, e: Entry) -> Result<Entry, String> {
+        let e = self.insert(e)?;
+        self.save()?;
+        Ok(e)
+    }
+
+    fn insert(&mut self, mut e: Entry) -> Result<Entry, String> {
+        self.validate(&mut e, &[])?;
         // generate ID
         let next_id = match self.entries.iter().map(|x| x.id).max() {
             Some(x) => x + 1,
             None => 1,
         };
         e.id = next_id;
-        // set timestamps
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        e.updated_at = now;
+        // set timestamps, keeping when an imported entry was last changed
+        let now = now();
+        if e.updated_at == 0 {
+            e.updated_at = now;
+        }
         e.created_at = now;
-        // push and save
+        self.phonetic.insert(&e);
         self.entries.push(e.clone());
-        match self.save() {
-            Ok(()) => (),
-            Err(err) => return Err(err),
-        };
         Ok(e)
     }
 
+    fn flush(&self) -> Result<(), String> {
+        self.save()
+    }
+
     fn update(&mut self, id: u16, e: Entry) -> Result<Entry, String> {
-        let to_update = match self.entries.iter_mut().find(|x| (**x).id == id) {
-            Some(x) => x,
+        let index = match self.entries.iter().position(|x| x.id == id) {
+            Some(i) => i,
             None => return Err(format!("could not find entry with ID {}", id)),
         };
+        let mut updated = self.entries[index].clone();
+        let to_update = &mut updated;
         to_update.first_name = e.first_name;
         to_update.last_name = e.last_name;
         to_update.phone = e.phone;
+        to_update.tags = e.tags;
         if e.address.eq("--") {
             to_update.address = "".to_string();
         } else {
@@ -95,28 +577,24 @@ impl DB for FileDB {
         } else {
             to_update.e_mail = e.e_mail;
         }
-        to_update.updated_at = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let return_entry: Entry = to_update.clone();
+        self.validate(to_update, &[id])?;
+        to_update.updated_at = now();
+        let return_entry: Entry = updated.clone();
+        self.entries[index] = updated;
+        self.phonetic.insert(&return_entry);
         match self.save() {
             Ok(()) => Ok(return_entry),
-            Err(msg) => Err(msg)
+            Err(msg) => Err(msg),
         }
     }
 
     fn delete(&mut self, id: u16) -> Result<Entry, String> {
-        let entry_index = match self
-            .entries
-            .iter()
-            .enumerate()
-            .find(|(_i, e)| (*e).id == id)
-        {
+        let entry_index = match self.entries.iter().enumerate().find(|(_i, e)| e.id == id) {
             Some((i, _)) => i,
             None => return Err(format!("could not find entry with ID {}", id)),
         };
         let entry = self.entries.remove(entry_index);
+        self.phonetic.remove(id);
         match self.save() {
             Ok(()) => (),
             Err(e) => return Err(e),
@@ -129,25 +607,282 @@ impl DB for FileDB {
     }
 
     fn read_by_id(&self, id: u16) -> Option<Entry> {
-        match self.entries.iter().find(|e| e.id == id) {
-            Some(e) => Some(e.clone()),
-            None => None,
-        }
+        self.entries.iter().find(|e| e.id == id).cloned()
     }
 
-    fn search(&self, term: String) -> Vec<Entry> {
+    fn search(&self, query: &Query) -> Vec<Entry> {
         self.entries
             .iter()
-            .filter(|&e| {
-                let mut name = e.first_name.clone();
-                name.push(' ');
-                name.push_str(e.last_name.as_str());
-                return name.contains(term.as_str())
-                    || e.phone.contains(term.as_str())
-                    || e.address.contains(term.as_str())
-                    || e.e_mail.contains(term.as_str());
+            .filter(|&e| query.matches(e))
+            .cloned()
+            .collect()
+    }
+
+    fn fuzzy_search(&self, term: &str, options: MatchOptions) -> Vec<Match> {
+        fuzzy::rank(self.entries.iter(), term, options)
+    }
+
+    fn phonetic_search(&self, term: &str) -> Vec<Entry> {
+        let codes = phonetic::encode_words(term);
+        self.entries
+            .iter()
+            .filter(|e| self.phonetic.matches(e.id, &codes))
+            .cloned()
+            .collect()
+    }
+
+    fn lookup(&self, number: &str) -> Vec<Entry> {
+        // with a region set, national numbers can be compared including
+        // their country code
+        let number = match PhoneNumber::parse(number, self.region) {
+            Ok(Some(n)) => PhoneDigits::parse(n.e164().as_str()),
+            _ => PhoneDigits::parse(number),
+        };
+        let number = match number {
+            Some(x) => x,
+            None => return Vec::new(),
+        };
+        let mut found: Vec<(PhoneMatch, &Entry)> = self
+            .entries
+            .iter()
+            .filter_map(|e| {
+                let phone = match e.phone_e164.is_empty() {
+                    true => e.phone.as_str(),
+                    false => e.phone_e164.as_str(),
+                };
+                PhoneDigits::parse(phone)
+                    .and_then(|p| p.compare(&number))
+                    .map(|m| (m, e))
             })
-            .map(|e| e.clone())
+            .collect();
+        // exact matches first, stable sort keeps file order otherwise
+        found.sort_by_key(|(m, _)| Reverse(*m));
+        found.into_iter().map(|(_, e)| e.clone()).collect()
+    }
+
+    fn duplicates(&self, min_confidence: u8) -> Vec<Duplicate> {
+        duplicates::find_duplicates(&self.entries, min_confidence)
+    }
+
+    fn merge(&mut self, keep: u16, other: u16) -> Result<Entry, String> {
+        if keep == other {
+            return Err("can not merge entry with itself".to_string());
+        }
+        let keep_index = match self.entries.iter().position(|x| x.id == keep) {
+            Some(i) => i,
+            None => return Err(format!("could not find entry with ID {}", keep)),
+        };
+        let other_index = match self.entries.iter().position(|x| x.id == other) {
+            Some(i) => i,
+            None => return Err(format!("could not find entry with ID {}", other)),
+        };
+        let mut merged = duplicates::merge(&self.entries[keep_index], &self.entries[other_index]);
+        self.validate(&mut merged, &[keep, other])?;
+        merged.updated_at = now();
+        self.entries[keep_index] = merged.clone();
+        self.entries.remove(other_index);
+        self.phonetic.insert(&merged);
+        self.phonetic.remove(other);
+        self.save()?;
+        Ok(merged)
+    }
+
+    fn find_existing(&self, e: &Entry) -> Vec<Entry> {
+        let mut e = e.clone();
+        e.phone_e164 = self.canonical_phone(e.phone.as_str());
+        self.entries
+            .iter()
+            .filter(|x| duplicates::shares_contact(x, &e))
+            .cloned()
             .collect()
     }
+
+    fn check(&self, e: &Entry) -> Result<(), String> {
+        self.validate(&mut e.clone(), &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn init_new_phonebook() {
+        let path = std::env::temp_dir().join(format!("phonebook-init-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let mut meta = Metadata::default();
+        meta.set("name", "Family");
+        meta.set("unique", "nickname");
+        assert_eq!(
+            Err("invalid rules in DB header: unknown field nickname".to_string()),
+            FileDB::init(path.clone(), &meta).map(|_| ())
+        );
+        assert!(fs::metadata(path.as_str()).is_err());
+
+        meta.set("unique", "phone");
+        let db = FileDB::init(path.clone(), &meta).unwrap();
+        assert!(db.read_all().is_empty());
+        assert_eq!(
+            "#phonebook=2\n#name=Family\n#unique=phone\n",
+            fs::read_to_string(path.as_str()).unwrap()
+        );
+        assert_eq!(
+            Err(format!("{} already exists", path)),
+            FileDB::init(path.clone(), &meta).map(|_| ())
+        );
+        assert!(FileDB::new(path.clone()).is_ok());
+
+        fs::write(path.as_str(), "#phonebook=3").unwrap();
+        assert_eq!(
+            Err("failed to load DB: unsupported phonebook format 3".to_string()),
+            FileDB::new(path.clone()).map(|_| ())
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn has_content() {
+        let path =
+            std::env::temp_dir().join(format!("phonebook-content-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert!(!FileDB::has_content(path));
+        fs::write(path, "").unwrap();
+        assert!(!FileDB::has_content(path));
+        fs::write(path, [0xff, 0xfe, 0x00]).unwrap();
+        assert!(FileDB::has_content(path));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn insert_keeps_update_time() {
+        let path =
+            std::env::temp_dir().join(format!("phonebook-insert-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let mut db = FileDB::init(path.clone(), &Metadata::default()).unwrap();
+        let entry = |first_name: &str, updated_at: u64| Entry {
+            first_name: first_name.to_string(),
+            last_name: "Smith".to_string(),
+            phone: format!("+1 555 123 {:04}", updated_at % 10000),
+            updated_at,
+            ..Default::default()
+        };
+        let imported = db.insert(entry("John", 815178430)).unwrap();
+        assert_eq!(815178430, imported.updated_at);
+        assert!(imported.created_at > imported.updated_at);
+        let created = db.create(entry("Mary", 0)).unwrap();
+        assert_eq!(created.created_at, created.updated_at);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn export_and_erase_subject_with_reused_id() {
+        let path = std::env::temp_dir().join(format!("phonebook-gdpr-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let header = "#phonebook=2\n#snapshots=10\n1;Ann;Lee;;;;500;500\n";
+        // Bob had ID 2 before he was deleted and Carol was given it
+        fs::write(path.as_str(), format!("{}2;Bob;Jones;;;;1000;1000", header)).unwrap();
+        snapshot::take(path.as_str(), 1500).unwrap();
+        fs::write(
+            path.as_str(),
+            format!("{}2;Carol;King;;;;2000;2000", header),
+        )
+        .unwrap();
+        audit::record(path.as_str(), 1200, "erased", 2).unwrap();
+        audit::record(path.as_str(), 2500, "erased", 2).unwrap();
+        let mut db = FileDB::new(path.clone()).unwrap();
+
+        let carol = db.subject(2).unwrap();
+        assert_eq!("Carol", carol.entry.unwrap().first_name);
+        assert!(carol.snapshots.is_empty());
+        assert_eq!(
+            vec![2500],
+            carol.audit.iter().map(|r| r.time).collect::<Vec<u64>>()
+        );
+        assert_eq!(1, db.subject(1).unwrap().snapshots.len());
+
+        assert_eq!(Ok(0), db.erase(2));
+        assert!(db.read_by_id(2).is_none());
+        let bob = db.subject(2).unwrap();
+        assert!(bob.entry.is_none());
+        assert_eq!("Bob", bob.snapshots[0].1.first_name);
+        assert_eq!(Ok(1), db.erase(2));
+        assert!(db.subject(2).is_err());
+        assert_eq!(1, db.subject(1).unwrap().snapshots.len());
+
+        fs::remove_dir_all(snapshot::dir(path.as_str())).unwrap();
+        fs::remove_file(audit::path(path.as_str())).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn restore_takes_snapshot() {
+        let path =
+            std::env::temp_dir().join(format!("phonebook-restore-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        fs::write(path.as_str(), "#phonebook=2\n1;Ann;Lee;;;;500;500\n").unwrap();
+        let archive = FileDB::new(path.clone()).unwrap().backup().unwrap();
+        assert!(FileDB::restore(path.clone(), archive.as_str(), true, None).is_ok());
+        assert!(snapshot::list(path.as_str()).unwrap().is_empty());
+
+        fs::write(
+            path.as_str(),
+            "#phonebook=2\n#snapshots=10\n2;Bob;Jones;;;;1000;1000\n",
+        )
+        .unwrap();
+        assert!(FileDB::restore(path.clone(), archive.as_str(), false, None).is_err());
+        let db = FileDB::restore(path.clone(), archive.as_str(), true, None).unwrap();
+        assert_eq!("Ann", db.read_all()[0].first_name);
+        let snapshots = snapshot::list(path.as_str()).unwrap();
+        assert_eq!(1, snapshots.len());
+        let content = snapshot::read(path.as_str(), snapshots[0].name.as_str()).unwrap();
+        assert!(content.contains("Bob"));
+
+        fs::remove_dir_all(snapshot::dir(path.as_str())).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn set_key_encrypts_snapshots() {
+        let path = std::env::temp_dir().join(format!("phonebook-key-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let key_file = format!("{}.key", path);
+        let new_key_file = format!("{}.newkey", path);
+        fs::write(key_file.as_str(), [1u8; 32]).unwrap();
+        fs::write(new_key_file.as_str(), [2u8; 32]).unwrap();
+        let header = "#phonebook=2\n#snapshots=10\n";
+        fs::write(path.as_str(), format!("{}2;Bob;Jones;;;;1000;1000", header)).unwrap();
+        snapshot::take(path.as_str(), 1500).unwrap();
+        fs::write(path.as_str(), format!("{}1;Ann;Lee;;;;500;500", header)).unwrap();
+        let mut db = FileDB::open(path.clone(), None).unwrap();
+
+        assert_eq!(
+            Ok(Vec::new()),
+            db.set_key(Some(Key::File(key_file.clone())))
+        );
+        let name = db.snapshots().unwrap()[0].name.clone();
+        let content = snapshot::read(path.as_str(), name.as_str()).unwrap();
+        assert!(crypt::is_encrypted(content.as_str()));
+        assert_eq!(
+            Ok(Vec::new()),
+            db.set_key(Some(Key::File(new_key_file.clone())))
+        );
+        let mut db = FileDB::open(path.clone(), Some(Key::File(new_key_file.clone()))).unwrap();
+        assert_eq!("Bob", db.subject(2).unwrap().snapshots[0].1.first_name);
+        assert!(db.restore_snapshot(name.as_str()).is_ok());
+        assert_eq!("Bob", db.read_all()[0].first_name);
+
+        let archive = db.backup().unwrap();
+        assert!(crypt::is_encrypted(archive.as_str()));
+        assert!(FileDB::restore(path.clone(), archive.as_str(), true, None).is_err());
+        let new_key = Some(Key::File(new_key_file.clone()));
+        let db = FileDB::restore(path.clone(), archive.as_str(), true, new_key).unwrap();
+        assert!(db.is_encrypted());
+        assert_eq!("Bob", db.read_all()[0].first_name);
+
+        fs::remove_dir_all(snapshot::dir(path.as_str())).unwrap();
+        fs::remove_file(key_file).unwrap();
+        fs::remove_file(new_key_file).unwrap();
+        fs::remove_file(path).unwrap();
+    }
 }
@@ -0,0 +1,215 @@
+use crate::entry::Entry;
+use crate::fuzzy;
+use crate::normalize::{self, MatchOptions};
+use crate::phone::{PhoneDigits, PhoneMatch};
+use std::cmp::Reverse;
+
+/// Pair of entries that probably describe the same person.
+#[derive(Clone)]
+pub struct Duplicate {
+    pub first: Entry,
+    pub second: Entry,
+    /// Likelihood in percent that both entries describe the same person.
+    pub confidence: u8,
+    /// Why the entries are considered duplicates, e.g. "same phone".
+    pub reasons: Vec<String>,
+}
+
+// Likelihood that two entries are the same person given a single piece of
+// evidence. Evidence is combined as independent probabilities.
+const SAME_PHONE: f64 = 0.6;
+const SAME_EMAIL: f64 = 0.6;
+const SAME_NAME: f64 = 0.5;
+const SIMILAR_NAME: f64 = 0.3;
+
+/// Finds pairs of entries which are likely duplicates, most likely first.
+pub fn find_duplicates(entries: &[Entry], min_confidence: u8) -> Vec<Duplicate> {
+    let mut result: Vec<Duplicate> = Vec::new();
+    for (i, a) in entries.iter().enumerate() {
+        for b in entries.iter().skip(i + 1) {
+            if let Some(d) = compare(a, b) {
+                if d.confidence >= min_confidence {
+                    result.push(d);
+                }
+            }
+        }
+    }
+    result.sort_by_key(|d| Reverse(d.confidence));
+    result
+}
+
+/// Compares two entries, returning None if nothing suggests they are the
+/// same person.
+pub fn compare(a: &Entry, b: &Entry) -> Option<Duplicate> {
+    let mut evidence: Vec<(f64, &str)> = Vec::new();
+    if same_phone(a, b) {
+        evidence.push((SAME_PHONE, "same phone"));
+    }
+    if same_email(a, b) {
+        evidence.push((SAME_EMAIL, "same e-mail"));
+    }
+    let name_a = normalize::normalize(
+        format!("{} {}", a.first_name, a.last_name).trim(),
+        MatchOptions::default(),
+    );
+    let name_b = normalize::normalize(
+        format!("{} {}", b.first_name, b.last_name).trim(),
+        MatchOptions::default(),
+    );
+    if !name_a.is_empty() && name_a == name_b {
+        evidence.push((SAME_NAME, "same name"));
+    } else if similar_names(&name_a, &name_b) {
+        evidence.push((SIMILAR_NAME, "similar name"));
+    }
+    if evidence.is_empty() {
+        return None;
+    }
+    let unlikely: f64 = evidence.iter().map(|(p, _)| 1.0 - p).product();
+    Some(Duplicate {
+        first: a.clone(),
+        second: b.clone(),
+        confidence: ((1.0 - unlikely) * 100.0).round() as u8,
+        reasons: evidence.iter().map(|(_, r)| r.to_string()).collect(),
+    })
+}
+
+/// Returns true if both entries have the same phone number or e-mail.
+pub fn shares_contact(a: &Entry, b: &Entry) -> bool {
+    same_phone(a, b) || same_email(a, b)
+}
+
+fn same_email(a: &Entry, b: &Entry) -> bool {
+    !a.e_mail.trim().is_empty()
+        && normalize::equals(a.e_mail.trim(), b.e_mail.trim(), MatchOptions::default())
+}
+
+/// Returns true if both entries have the same phone number, comparing the
+/// E.164 form where known.
+pub(crate) fn same_phone(a: &Entry, b: &Entry) -> bool {
+    let canonical = |e: &Entry| match e.phone_e164.is_empty() {
+        true => PhoneDigits::parse(&e.phone),
+        false => PhoneDigits::parse(&e.phone_e164),
+    };
+    match (canonical(a), canonical(b)) {
+        (Some(x), Some(y)) => x.compare(&y) == Some(PhoneMatch::Exact),
+        _ => false,
+    }
+}
+
+/// Names are similar if they differ by at most one typo per five letters.
+fn similar_names(a: &str, b: &str) -> bool {
+    let len = a.chars().count().max(b.chars().count());
+    if len < 5 {
+        return false;
+    }
+    fuzzy::edit_distance(a, b) <= len / 5
+}
+
+/// Combines two entries describing the same person into one.
+///
+/// The result has the ID of `keep`. Fields empty in `keep` are taken from
+/// `other`, tags of both entries are combined, and the earlier creation time
+/// is kept.
+pub fn merge(keep: &Entry, other: &Entry) -> Entry {
+    let pick = |a: &String, b: &String| match a.trim().is_empty() {
+        true => b.clone(),
+        false => a.clone(),
+    };
+    let mut tags = keep.tags.clone();
+    for t in other.tags.iter() {
+        if !tags.contains(t) {
+            tags.push(t.clone());
+        }
+    }
+    let mut custom = other.custom.clone();
+    custom.extend(keep.custom.clone());
+    let (phone, phone_e164) = match keep.phone.trim().is_empty() {
+        true => (other.phone.clone(), other.phone_e164.clone()),
+        false => (keep.phone.clone(), keep.phone_e164.clone()),
+    };
+    Entry {
+        id: keep.id,
+        first_name: pick(&keep.first_name, &other.first_name),
+        last_name: pick(&keep.last_name, &other.last_name),
+        phone,
+        address: pick(&keep.address, &other.address),
+        e_mail: pick(&keep.e_mail, &other.e_mail),
+        created_at: keep.created_at.min(other.created_at),
+        updated_at: keep.updated_at.max(other.updated_at),
+        tags,
+        phone_e164,
+        organization: pick(&keep.organization, &other.organization),
+        note: pick(&keep.note, &other.note),
+        birthday: pick(&keep.birthday, &other.birthday),
+        uid: pick(&keep.uid, &other.uid),
+        title: pick(&keep.title, &other.title),
+        custom,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u16, first_name: &str, last_name: &str, phone: &str, e_mail: &str) -> Entry {
+        Entry {
+            id,
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            phone: phone.to_string(),
+            e_mail: e_mail.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn find_by_phone_email_and_name() {
+        let entries = [
+            entry(1, "John", "Smith", "+1 555 123 4567", "john@example.com"),
+            entry(2, "Jon", "Smith", "(555) 123-4567", ""),
+            entry(3, "Mary", "Jones", "+1 555 765 4321", "JOHN@example.com"),
+            entry(4, "Peter", "Brown", "+1 555 000 1111", ""),
+        ];
+        let found = find_duplicates(&entries, 0);
+        let pairs: Vec<(u16, u16, u8)> = found
+            .iter()
+            .map(|d| (d.first.id, d.second.id, d.confidence))
+            .collect();
+        assert_eq!(vec![(1, 2, 72), (1, 3, 60)], pairs);
+        assert_eq!(vec!["same phone", "similar name"], found[0].reasons);
+        assert_eq!(1, find_duplicates(&entries, 70).len());
+    }
+
+    #[test]
+    fn merge_fills_empty_fields() {
+        let mut keep = entry(1, "John", "Smith", "+1 555 123 4567", "");
+        keep.tags = vec!["work".to_string()];
+        keep.created_at = 20;
+        let mut other = entry(2, "Jon", "Smith", "555 000 1111", "john@example.com");
+        other.address = "Main street 1".to_string();
+        other.tags = vec!["work".to_string(), "friends".to_string()];
+        other.created_at = 10;
+        let merged = merge(&keep, &other);
+        assert_eq!(1, merged.id);
+        assert_eq!("John", merged.first_name);
+        assert_eq!("+1 555 123 4567", merged.phone);
+        assert_eq!("john@example.com", merged.e_mail);
+        assert_eq!("Main street 1", merged.address);
+        assert_eq!(vec!["work", "friends"], merged.tags);
+        assert_eq!(10, merged.created_at);
+    }
+
+    #[test]
+    fn shared_contact() {
+        let a = entry(1, "John", "Smith", "+1 555 123 4567", "john@example.com");
+        assert!(shares_contact(
+            &a,
+            &entry(2, "Mary", "Jones", "", "John@Example.com")
+        ));
+        assert!(shares_contact(
+            &a,
+            &entry(2, "Mary", "Jones", "(555) 123-4567", "")
+        ));
+        assert!(!shares_contact(&a, &entry(2, "John", "Smith", "", "")));
+    }
+}
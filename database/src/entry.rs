@@ -1,18 +1,70 @@
+use crate::phone::{PhoneFormat, PhoneNumber};
+use crate::query::Field;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Phonebook entry. With the `json` feature it serializes with the field
+/// names below, except `e_mail` which is `email`.
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "json", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "json", serde(default))]
 pub struct Entry {
     pub id: u16,
     pub first_name: String,
     pub last_name: String,
     pub phone: String,
     pub address: String,
+    #[cfg_attr(feature = "json", serde(rename = "email"))]
     pub e_mail: String,
     pub created_at: u64,
     pub updated_at: u64,
+    pub tags: Vec<String>,
+    /// Canonical E.164 form of `phone`, empty if the number could not be
+    /// determined because it has no country code and no region was set.
+    pub phone_e164: String,
+    pub organization: String,
+    pub note: String,
+    /// Birthday as `YYYY-MM-DD`, or `--MM-DD` if the year is not known.
+    pub birthday: String,
+    /// Identifier of the contact in other address books, e.g. a vCard UID.
+    pub uid: String,
+    /// Job title.
+    pub title: String,
+    /// Values without a field of their own, e.g. from imported address
+    /// books, by name.
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "BTreeMap::is_empty"))]
+    pub custom: BTreeMap<String, String>,
 }
 
 impl Entry {
     pub fn from(s: &str) -> Result<Entry, String> {
-        let parts: Vec<&str> = s.split(';').collect();
-        if parts.len() != 8 {
+        let parts = split_line(s);
+        // custom fields are one column of escaped name=value values
+        let custom = match parts.get(15) {
+            Some(column) => split_line(column)
+                .into_iter()
+                .filter(|x| !x.is_empty())
+                .collect(),
+            None => Vec::new(),
+        };
+        Entry::parse(parts, custom)
+    }
+
+    /// Reads a line of a phonebook written in an older format, in which every
+    /// custom field has a column of its own. Before format version 1 values
+    /// were not escaped and backslashes are literal.
+    pub(crate) fn from_older(s: &str, escaped: bool) -> Result<Entry, String> {
+        let parts: Vec<String> = match escaped {
+            true => split_line(s),
+            false => s.split(';').map(|x| x.to_string()).collect(),
+        };
+        let custom = parts.iter().skip(15).cloned().collect();
+        Entry::parse(parts, custom)
+    }
+
+    fn parse(parts: Vec<String>, custom_fields: Vec<String>) -> Result<Entry, String> {
+        // columns after updated_at were added later and may be missing
+        if parts.len() < 8 {
             return Err("wrong number of arguments".to_string());
         }
         let id = match parts[0].parse::<u16>() {
@@ -39,55 +91,199 @@ impl Entry {
                 return Err(err);
             }
         };
+        let mut custom = BTreeMap::new();
+        for part in custom_fields.iter() {
+            match part.split_once('=') {
+                Some((name, value)) => custom.insert(name.to_string(), value.to_string()),
+                None => return Err(format!("custom field {} has no value", part)),
+            };
+        }
         Ok(Entry {
             id,
-            first_name: parts[1].to_string(),
-            last_name: parts[2].to_string(),
-            phone: parts[3].to_string(),
-            address: parts[4].to_string(),
-            e_mail: parts[5].to_string(),
+            first_name: parts[1].clone(),
+            last_name: parts[2].clone(),
+            phone: parts[3].clone(),
+            address: parts[4].clone(),
+            e_mail: parts[5].clone(),
             created_at,
             updated_at,
+            tags: parts
+                .get(8)
+                .map(|s| Entry::parse_tags(s))
+                .unwrap_or_default(),
+            phone_e164: parts.get(9).cloned().unwrap_or_default(),
+            organization: parts.get(10).cloned().unwrap_or_default(),
+            note: parts.get(11).cloned().unwrap_or_default(),
+            birthday: parts.get(12).cloned().unwrap_or_default(),
+            uid: parts.get(13).cloned().unwrap_or_default(),
+            title: parts.get(14).cloned().unwrap_or_default(),
+            custom,
         })
     }
 
-    pub fn to_string(&self) -> String {
-        [
-            format!("{}", self.id).as_str(),
-            self.first_name.as_str(),
-            self.last_name.as_str(),
-            self.phone.as_str(),
-            self.address.as_str(),
-            self.e_mail.as_str(),
-            format!("{}", self.created_at).as_str(),
-            format!("{}", self.updated_at).as_str(),
-        ]
-        .join(";")
+    /// Returns the phone number in the given format, or as typed if its
+    /// canonical form is not known.
+    pub fn formatted_phone(&self, format: PhoneFormat) -> String {
+        match (format, PhoneNumber::from_e164(&self.phone_e164)) {
+            (PhoneFormat::Original, _) | (_, None) => self.phone.clone(),
+            (_, Some(number)) => number.format(format),
+        }
+    }
+
+    /// Returns the value of a field as text. Tags are joined with commas and
+    /// the name is first and last name.
+    pub fn get(&self, field: Field) -> String {
+        match field {
+            Field::Id => self.id.to_string(),
+            Field::Name => format!("{} {}", self.first_name, self.last_name)
+                .trim()
+                .to_string(),
+            Field::FirstName => self.first_name.clone(),
+            Field::LastName => self.last_name.clone(),
+            Field::Phone => self.phone.clone(),
+            Field::Address => self.address.clone(),
+            Field::Email => self.e_mail.clone(),
+            Field::Tag => self.tags.join(","),
+            Field::Organization => self.organization.clone(),
+            Field::Note => self.note.clone(),
+            Field::Birthday => self.birthday.clone(),
+            Field::Title => self.title.clone(),
+            Field::Any => "".to_string(),
+        }
+    }
+
+    /// Sets a field from text. A name is split into first name and the rest
+    /// as last name. The ID can not be set this way.
+    pub fn set(&mut self, field: Field, value: &str) {
+        let value = value.trim();
+        match field {
+            Field::Name => {
+                let (first, last) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+                self.first_name = first.to_string();
+                self.last_name = last.trim().to_string();
+            }
+            Field::FirstName => self.first_name = value.to_string(),
+            Field::LastName => self.last_name = value.to_string(),
+            Field::Phone => self.phone = value.to_string(),
+            Field::Address => self.address = value.to_string(),
+            Field::Email => self.e_mail = value.to_string(),
+            Field::Tag => self.tags = Entry::parse_tags(value),
+            Field::Organization => self.organization = value.to_string(),
+            Field::Note => self.note = value.to_string(),
+            Field::Birthday => self.birthday = value.to_string(),
+            Field::Title => self.title = value.to_string(),
+            Field::Id | Field::Any => (),
+        }
+    }
+
+    /// Splits a comma separated list of tags, dropping empty ones.
+    pub fn parse_tags(s: &str) -> Vec<String> {
+        s.split(',')
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .map(|t| t.to_string())
+            .collect()
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut line = vec![
+            format!("{}", self.id),
+            escape(&self.first_name),
+            escape(&self.last_name),
+            escape(&self.phone),
+            escape(&self.address),
+            escape(&self.e_mail),
+            format!("{}", self.created_at),
+            format!("{}", self.updated_at),
+            escape(&self.tags.join(",")),
+            self.phone_e164.clone(),
+            escape(&self.organization),
+            escape(&self.note),
+            escape(&self.birthday),
+            escape(&self.uid),
+            escape(&self.title),
+        ];
+        if !self.custom.is_empty() {
+            let custom: Vec<String> = self
+                .custom
+                .iter()
+                .map(|(name, value)| escape(format!("{}={}", name, value).as_str()))
+                .collect();
+            line.push(escape(custom.join(";").as_str()));
+        }
+        write!(f, "{}", line.join(";"))
     }
 }
 
-impl Clone for Entry {
-    fn clone(&self) -> Self {
-        Entry {
-            id: self.id.clone(),
-            first_name: self.first_name.clone(),
-            last_name: self.last_name.clone(),
-            phone: self.phone.clone(),
-            address: self.address.clone(),
-            e_mail: self.e_mail.clone(),
-            created_at: self.created_at.clone(),
-            updated_at: self.updated_at.clone(),
+/// Escapes the separator, line breaks and backslashes of a stored value.
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            ';' => result.push_str("\\;"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            _ => result.push(c),
         }
     }
+    result
+}
+
+/// Splits a stored line at unescaped separators and unescapes the values.
+fn split_line(s: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().unwrap();
+        match c {
+            ';' => parts.push(String::new()),
+            '\\' => match chars.next() {
+                Some('n') => part.push('\n'),
+                Some('r') => part.push('\r'),
+                Some(x) => part.push(x),
+                None => part.push('\\'),
+            },
+            _ => part.push(c),
+        }
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separators_in_values_round_trip() {
+        let e = Entry {
+            id: 4,
+            first_name: "John".to_string(),
+            last_name: "Smith; Jr.".to_string(),
+            address: "Main street 1\nC:\\Town".to_string(),
+            tags: vec!["work".to_string()],
+            custom: BTreeMap::from([("nick".to_string(), "Johnny; J".to_string())]),
+            ..Default::default()
+        };
+        let line = e.to_string();
+        assert_eq!(
+            "4;John;Smith\\; Jr.;;Main street 1\\nC:\\\\Town;;0;0;work;;;;;;;nick=Johnny\\\\\\; J",
+            line
+        );
+        let read = Entry::from(&line).unwrap();
+        assert_eq!(e.last_name, read.last_name);
+        assert_eq!(e.address, read.address);
+        assert_eq!(e.custom, read.custom);
+    }
 
-    fn clone_from(&mut self, source: &Self) {
-        self.id = source.id.clone();
-        self.first_name = source.first_name.clone();
-        self.last_name = source.last_name.clone();
-        self.phone = source.phone.clone();
-        self.address = source.address.clone();
-        self.e_mail = source.e_mail.clone();
-        self.created_at = source.created_at.clone();
-        self.updated_at = source.updated_at.clone();
+    #[test]
+    fn set_name() {
+        let mut e = Entry::default();
+        e.set(Field::Name, " Mary Ann  Jones ");
+        assert_eq!("Mary", e.first_name);
+        assert_eq!("Ann  Jones", e.last_name);
+        assert_eq!("Mary Ann  Jones", e.get(Field::Name));
     }
 }
@@ -0,0 +1,212 @@
+use crate::entry::Entry;
+use crate::normalize::{self, MatchOptions};
+
+/// Entry found by a fuzzy search together with its relevance.
+#[derive(Clone)]
+pub struct Match {
+    pub entry: Entry,
+    pub score: u32,
+}
+
+// Scores of the different kinds of matches. The bands do not overlap, so an
+// exact match always outranks a prefix match, which outranks a fuzzy match,
+// regardless of the field the word was found in.
+const EXACT: u32 = 400;
+const PREFIX: u32 = 300;
+const CONTAINS: u32 = 200;
+const FUZZY: u32 = 100;
+const FUZZY_RANGE: f64 = 50.0;
+
+// Bonus added on top of the match kind depending on the field matched.
+const NAME_BONUS: u32 = 40;
+const CONTACT_BONUS: u32 = 20;
+const ADDRESS_BONUS: u32 = 0;
+
+/// Scores entries against a free text term and returns those where every
+/// word of the term matched, best matches first.
+///
+/// Each word of the term is compared with the words of the entry's fields.
+/// A word matches exactly, as a prefix, as a substring, or, when it is
+/// within a small edit distance of a field word, fuzzily. The entry's score
+/// is the sum of the best score of every term word.
+pub fn rank<'a, I>(entries: I, term: &str, options: MatchOptions) -> Vec<Match>
+where
+    I: Iterator<Item = &'a Entry>,
+{
+    let words: Vec<String> = split_words(&normalize::normalize(term, options));
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let mut result: Vec<Match> = entries
+        .filter_map(|e| {
+            score_entry(e, &words, options).map(|score| Match {
+                entry: e.clone(),
+                score,
+            })
+        })
+        .collect();
+    result.sort_by(|a, b| b.score.cmp(&a.score).then(a.entry.id.cmp(&b.entry.id)));
+    result
+}
+
+fn score_entry(e: &Entry, words: &[String], options: MatchOptions) -> Option<u32> {
+    let fields: [(&str, u32); 5] = [
+        (e.first_name.as_str(), NAME_BONUS),
+        (e.last_name.as_str(), NAME_BONUS),
+        (e.e_mail.as_str(), CONTACT_BONUS),
+        (e.phone.as_str(), CONTACT_BONUS),
+        (e.address.as_str(), ADDRESS_BONUS),
+    ];
+    let tokens: Vec<(String, u32)> = fields
+        .iter()
+        .flat_map(|(value, bonus)| {
+            split_words(&normalize::normalize(value, options))
+                .into_iter()
+                .map(move |t| (t, *bonus))
+        })
+        .collect();
+    let mut total = 0;
+    for word in words {
+        let best = tokens
+            .iter()
+            .filter_map(|(token, bonus)| score_word(word, token).map(|s| s + bonus))
+            .max()?;
+        total += best;
+    }
+    Some(total)
+}
+
+fn score_word(word: &str, token: &str) -> Option<u32> {
+    if word == token {
+        return Some(EXACT);
+    }
+    if token.starts_with(word) {
+        return Some(PREFIX);
+    }
+    if token.contains(word) {
+        return Some(CONTAINS);
+    }
+    let word_len = word.chars().count();
+    let allowed = max_distance(word_len);
+    if allowed == 0 {
+        return None;
+    }
+    let distance = edit_distance(word, token);
+    if distance > allowed {
+        return None;
+    }
+    let longest = word_len.max(token.chars().count()) as f64;
+    let similarity = 1.0 - distance as f64 / longest;
+    Some(FUZZY + (similarity * FUZZY_RANGE).round() as u32)
+}
+
+/// Number of typos tolerated in a word of the given length. Short words are
+/// only matched exactly, as nearly every short word is close to another one.
+fn max_distance(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=6 => 1,
+        _ => 2,
+    }
+}
+
+fn split_words(s: &str) -> Vec<String> {
+    s.split(|c: char| !c.is_alphanumeric() && c != '+')
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_string())
+        .collect()
+}
+
+/// Optimal string alignment distance: the Levenshtein distance extended with
+/// transposition of two adjacent characters, a common typing mistake.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows: Vec<Vec<usize>> = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut d = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = d.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = d;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: u16, first_name: &str, last_name: &str, address: &str) -> Entry {
+        Entry {
+            id,
+            first_name: first_name.to_string(),
+            last_name: last_name.to_string(),
+            address: address.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(0, edit_distance("smith", "smith"));
+        assert_eq!(1, edit_distance("smith", "smyth"));
+        assert_eq!(1, edit_distance("smith", "smtih"));
+        assert_eq!(2, edit_distance("jonathan", "jonahtn"));
+        assert_eq!(3, edit_distance("", "abc"));
+    }
+
+    #[test]
+    fn typos_are_found() {
+        let entries = [
+            entry(1, "Jonathan", "Smith", ""),
+            entry(2, "Mary", "Jones", ""),
+        ];
+        let result = rank(entries.iter(), "jonahtan smiht", MatchOptions::default());
+        assert_eq!(1, result.len());
+        assert_eq!(1, result[0].entry.id);
+    }
+
+    #[test]
+    fn exact_before_prefix_before_fuzzy() {
+        let entries = [
+            entry(1, "Anna", "Smithson", ""),
+            entry(2, "Anna", "Smyth", ""),
+            entry(3, "Anna", "Smith", ""),
+        ];
+        let ids: Vec<u16> = rank(entries.iter(), "smith", MatchOptions::default())
+            .iter()
+            .map(|m| m.entry.id)
+            .collect();
+        assert_eq!(vec![3, 1, 2], ids);
+    }
+
+    #[test]
+    fn name_before_address() {
+        let entries = [
+            entry(1, "Anna", "Jones", "Baker street"),
+            entry(2, "Anna", "Baker", ""),
+        ];
+        let result = rank(entries.iter(), "baker", MatchOptions::default());
+        assert_eq!(2, result[0].entry.id);
+        assert_eq!(1, result[1].entry.id);
+        assert!(result[0].score > result[1].score);
+    }
+
+    #[test]
+    fn short_words_are_not_fuzzy() {
+        let entries = [entry(1, "Ann", "Lee", "")];
+        assert!(rank(entries.iter(), "bob", MatchOptions::default()).is_empty());
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+
+/// Record of an import file that could not be read or stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowError {
+    /// Line of the file the record starts on, counting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Character encoding of an import file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Windows-1252, a superset of ISO-8859-1 used by most Windows programs.
+    Windows1252,
+}
+
+impl Encoding {
+    pub fn from(s: &str) -> Result<Encoding, String> {
+        match s.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            "windows-1252" | "cp1252" | "latin1" | "iso-8859-1" => Ok(Encoding::Windows1252),
+            _ => Err(format!("unknown encoding {}", s)),
+        }
+    }
+
+    /// Guesses the encoding from a byte order mark, falling back to
+    /// Windows-1252 if the content is not valid UTF-8.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        if bytes.starts_with(&[0xFF, 0xFE]) {
+            Encoding::Utf16Le
+        } else if bytes.starts_with(&[0xFE, 0xFF]) {
+            Encoding::Utf16Be
+        } else if std::str::from_utf8(bytes).is_ok() {
+            Encoding::Utf8
+        } else {
+            Encoding::Windows1252
+        }
+    }
+}
+
+// characters of Windows-1252 bytes 0x80 to 0x9F, the rest is ISO-8859-1
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// Decodes the content of an import file, detecting the encoding if none
+/// is given. A byte order mark is removed.
+pub fn decode(bytes: &[u8], encoding: Option<Encoding>) -> Result<String, String> {
+    let encoding = encoding.unwrap_or_else(|| Encoding::detect(bytes));
+    match encoding {
+        Encoding::Utf8 => {
+            let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+            match String::from_utf8(bytes.to_vec()) {
+                Ok(s) => Ok(s),
+                Err(e) => Err(format!("file is not valid UTF-8: {}", e)),
+            }
+        }
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            if !bytes.len().is_multiple_of(2) {
+                return Err("file is not valid UTF-16: odd number of bytes".to_string());
+            }
+            let units: Vec<u16> = bytes
+                .chunks(2)
+                .map(|c| match encoding {
+                    Encoding::Utf16Le => u16::from_le_bytes([c[0], c[1]]),
+                    _ => u16::from_be_bytes([c[0], c[1]]),
+                })
+                .collect();
+            let units = units.strip_prefix(&[0xFEFF]).unwrap_or(&units);
+            match String::from_utf16(units) {
+                Ok(s) => Ok(s),
+                Err(e) => Err(format!("file is not valid UTF-16: {}", e)),
+            }
+        }
+        Encoding::Windows1252 => Ok(bytes
+            .iter()
+            .map(|b| match b {
+                0x80..=0x9F => WINDOWS_1252[(b - 0x80) as usize],
+                _ => *b as char,
+            })
+            .collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_and_decode() {
+        assert_eq!(
+            Ok("Zoë".to_string()),
+            decode("\u{FEFF}Zoë".as_bytes(), None)
+        );
+        assert_eq!(
+            Ok("Zoë €".to_string()),
+            decode(&[0x5A, 0x6F, 0xEB, 0x20, 0x80], None)
+        );
+        let utf16: Vec<u8> = [0xFEFFu16, 0x5A, 0x6F, 0xEB]
+            .iter()
+            .flat_map(|u| u.to_le_bytes())
+            .collect();
+        assert_eq!(Encoding::Utf16Le, Encoding::detect(&utf16));
+        assert_eq!(Ok("Zoë".to_string()), decode(&utf16, None));
+        assert!(decode(&[0x5A, 0xEB], Some(Encoding::Utf8)).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use crate::db::Subject;
+use crate::entry::Entry;
+use crate::meta::Metadata;
+use crate::time::DateTime;
+use serde_json::{Map, Value};
+
+/// Line of a JSON Lines database.
+pub enum Line {
+    Metadata(Metadata),
+    Entry(Box<Entry>),
+}
+
+/// Writes entries as a JSON array.
+pub fn write_entries(entries: &[Entry]) -> Result<String, String> {
+    match serde_json::to_string_pretty(entries) {
+        Ok(s) => Ok(s + "\n"),
+        Err(e) => Err(format!("failed to write JSON: {}", e)),
+    }
+}
+
+/// Writes what is stored about a person as a JSON object with the entry,
+/// null if it was deleted, its copies in snapshots and its audit records.
+pub fn write_subject(subject: &Subject) -> Result<String, String> {
+    let to_value =
+        |e: &Entry| serde_json::to_value(e).map_err(|e| format!("failed to write JSON: {}", e));
+    let mut copies: Vec<Value> = Vec::new();
+    for (name, e) in subject.snapshots.iter() {
+        let mut copy = Map::new();
+        copy.insert("snapshot".to_string(), Value::String(name.clone()));
+        copy.insert("entry".to_string(), to_value(e)?);
+        copies.push(Value::Object(copy));
+    }
+    let mut records: Vec<Value> = Vec::new();
+    for r in subject.audit.iter() {
+        let mut record = Map::new();
+        let time = DateTime::from_timestamp(r.time).to_extended();
+        record.insert("time".to_string(), Value::String(time));
+        record.insert("action".to_string(), Value::String(r.action.clone()));
+        records.push(Value::Object(record));
+    }
+    let mut result = Map::new();
+    result.insert("id".to_string(), Value::from(subject.id));
+    result.insert(
+        "entry".to_string(),
+        subject
+            .entry
+            .as_ref()
+            .map(to_value)
+            .transpose()?
+            .unwrap_or(Value::Null),
+    );
+    result.insert("snapshots".to_string(), Value::Array(copies));
+    result.insert("audit".to_string(), Value::Array(records));
+    match serde_json::to_string_pretty(&Value::Object(result)) {
+        Ok(s) => Ok(s + "\n"),
+        Err(e) => Err(format!("failed to write JSON: {}", e)),
+    }
+}
+
+/// Formats metadata as a JSON Lines object `{"metadata":{...}}`.
+pub fn metadata_line(meta: &Metadata) -> String {
+    let values: Map<String, Value> = meta
+        .iter()
+        .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+        .collect();
+    let mut line = Map::new();
+    line.insert("metadata".to_string(), Value::Object(values));
+    Value::Object(line).to_string()
+}
+
+/// Formats an entry as a single line JSON object.
+pub fn entry_line(e: &Entry) -> Result<String, String> {
+    match serde_json::to_string(e) {
+        Ok(s) => Ok(s),
+        Err(e) => Err(format!("failed to write JSON: {}", e)),
+    }
+}
+
+/// Parses one line of a JSON Lines database.
+pub fn parse_line(line: &str) -> Result<Line, String> {
+    let value: Value = match serde_json::from_str(line) {
+        Ok(x) => x,
+        Err(e) => return Err(e.to_string()),
+    };
+    if let Some(values) = value.get("metadata").and_then(|m| m.as_object()) {
+        let mut meta = Metadata::default();
+        for (k, v) in values.iter() {
+            match v.as_str() {
+                Some(v) => meta.set(k, v),
+                None => return Err(format!("metadata {} must be a string", k)),
+            }
+        }
+        return Ok(Line::Metadata(meta));
+    }
+    match serde_json::from_value(value) {
+        Ok(e) => Ok(Line::Entry(Box::new(e))),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_field_names() {
+        let e = Entry {
+            id: 2,
+            first_name: "John".to_string(),
+            e_mail: "john@example.com".to_string(),
+            tags: vec!["work".to_string()],
+            ..Default::default()
+        };
+        let line = entry_line(&e).unwrap();
+        assert_eq!(
+            "{\"id\":2,\"first_name\":\"John\",\"last_name\":\"\",\"phone\":\"\",\"address\":\"\",\
+            \"email\":\"john@example.com\",\"created_at\":0,\"updated_at\":0,\"tags\":[\"work\"],\
+            \"phone_e164\":\"\",\"organization\":\"\",\"note\":\"\",\"birthday\":\"\",\"uid\":\"\",\"title\":\"\"}",
+            line
+        );
+        match parse_line("{\"id\":3,\"first_name\":\"Mary\",\"email\":\"m@example.com\"}") {
+            Ok(Line::Entry(e)) => {
+                assert_eq!(3, e.id);
+                assert_eq!("m@example.com", e.e_mail);
+            }
+            _ => panic!("entry expected"),
+        }
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let mut meta = Metadata::default();
+        meta.set("region", "US");
+        match parse_line(&metadata_line(&meta)) {
+            Ok(Line::Metadata(read)) => assert_eq!(meta, read),
+            _ => panic!("metadata expected"),
+        }
+        assert!(parse_line("{\"metadata\":{\"region\":1}}").is_err());
+        assert!(parse_line("not json").is_err());
+    }
+}
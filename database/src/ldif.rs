@@ -0,0 +1,416 @@
+use crate::base64;
+use crate::entry::Entry;
+use crate::import::RowError;
+use crate::query::Field;
+
+/// Attribute of an LDIF record with its decoded value.
+struct Attribute {
+    /// Name in lower case, without options such as `;lang-en`.
+    name: String,
+    value: String,
+}
+
+/// Joins folded lines, which continue on the next line starting with a
+/// space, and drops comments. Returns the lines with the line they start on;
+/// empty lines are kept as they separate records.
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut comment = false;
+    for (i, line) in text.split('\n').enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some(rest) = line.strip_prefix(' ') {
+            if let (false, Some(last)) = (comment, lines.last_mut()) {
+                last.1.push_str(rest);
+            }
+            continue;
+        }
+        comment = line.starts_with('#');
+        if !comment {
+            lines.push((i + 1, line.to_string()));
+        }
+    }
+    lines
+}
+
+/// Parses `name: value`, `name:: base64` or `name:< url`.
+fn parse_attribute(line: &str) -> Result<Attribute, String> {
+    let (name, value) = match line.split_once(':') {
+        Some(x) => x,
+        None => return Err(format!("line {} is not an attribute", line)),
+    };
+    let name = name.split(';').next().unwrap_or(name).trim().to_lowercase();
+    let value = match value.strip_prefix(':') {
+        Some(encoded) => match base64::decode(encoded) {
+            Ok(bytes) => match String::from_utf8(bytes) {
+                Ok(s) => s,
+                Err(_) => return Err(format!("value of {} is not UTF-8 text", name)),
+            },
+            Err(e) => return Err(format!("value of {}: {}", name, e)),
+        },
+        None if value.starts_with('<') => return Err(format!("value of {} refers to a URL", name)),
+        None => value.trim_start_matches(' ').to_string(),
+    };
+    Ok(Attribute { name, value })
+}
+
+/// Converts a postal address, whose lines are separated by `$`.
+fn postal_address(s: &str) -> String {
+    let lines: Vec<String> = s
+        .split('$')
+        .map(|l| {
+            l.replace("\\24", "$")
+                .replace("\\5C", "\\")
+                .replace("\\5c", "\\")
+                .trim()
+                .to_string()
+        })
+        .filter(|l| !l.is_empty())
+        .collect();
+    lines.join(", ")
+}
+
+/// Phone attributes in the order they are taken for the phone number, with
+/// their names as written. Further values are kept as custom fields by name.
+const PHONE_ATTRIBUTES: [(&str, &str); 3] = [
+    ("telephonenumber", "telephoneNumber"),
+    ("mobile", "mobile"),
+    ("homephone", "homePhone"),
+];
+
+fn record_entry(attrs: &[Attribute]) -> Result<Entry, String> {
+    if let Some(a) = attrs.iter().find(|a| a.name == "changetype") {
+        return Err(format!("change records ({}) are not supported", a.value));
+    }
+    let first = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|n| {
+                attrs
+                    .iter()
+                    .find(|a| a.name == *n && !a.value.trim().is_empty())
+            })
+            .map(|a| a.value.trim().to_string())
+            .unwrap_or_default()
+    };
+    let all = |name: &str| -> Vec<String> {
+        attrs
+            .iter()
+            .filter(|a| a.name == name && !a.value.trim().is_empty())
+            .map(|a| a.value.trim().to_string())
+            .collect()
+    };
+    let mut e = Entry {
+        first_name: first(&["givenname", "gn"]),
+        last_name: first(&["sn", "surname"]),
+        organization: first(&["o"]),
+        title: first(&["title"]),
+        note: first(&["description"]),
+        uid: first(&["uid"]),
+        ..Default::default()
+    };
+    // the first phone number and e-mail are the entry's, further values are
+    // kept as custom fields like abook and mutt do
+    for (name, key) in PHONE_ATTRIBUTES {
+        let mut values = all(name);
+        if e.phone.is_empty() && !values.is_empty() {
+            e.phone = values.remove(0);
+        }
+        if !values.is_empty() {
+            e.custom.insert(key.to_string(), values.join(", "));
+        }
+    }
+    let mut mails = all("mail");
+    if !mails.is_empty() {
+        e.e_mail = mails.remove(0);
+    }
+    if !mails.is_empty() {
+        e.custom.insert("email".to_string(), mails.join(", "));
+    }
+    let address = first(&["postaladdress", "homepostaladdress"]);
+    e.address = postal_address(&address);
+    // cn holds the full name, needed when given name or surname is missing
+    let cn = first(&["cn", "commonname"]);
+    if e.first_name.is_empty() && !cn.is_empty() {
+        match cn.strip_suffix(e.last_name.as_str()) {
+            Some(rest) if !e.last_name.is_empty() => e.first_name = rest.trim().to_string(),
+            _ => e.set(Field::Name, &cn),
+        }
+    }
+    if e.first_name.is_empty() && e.last_name.is_empty() {
+        return Err("record has no name".to_string());
+    }
+    Ok(e)
+}
+
+/// Reads the records of an LDIF file. Records that can not be read are
+/// reported with the line they start on, without stopping the import.
+pub fn read_entries(text: &str) -> (Vec<(usize, Entry)>, Vec<RowError>) {
+    let mut entries: Vec<(usize, Entry)> = Vec::new();
+    let mut errors: Vec<RowError> = Vec::new();
+    let mut records: Vec<(usize, Vec<(usize, String)>)> = Vec::new();
+    for (line, content) in unfold(text) {
+        match (content.trim().is_empty(), records.last_mut()) {
+            (true, _) => records.push((0, Vec::new())),
+            (false, Some((start, lines))) => {
+                if lines.is_empty() {
+                    *start = line;
+                }
+                lines.push((line, content));
+            }
+            (false, None) => records.push((line, vec![(line, content)])),
+        }
+    }
+    for (start, lines) in records.into_iter().filter(|(_, l)| !l.is_empty()) {
+        // the version line may come before the first record
+        if lines.len() == 1 && lines[0].1.to_lowercase().starts_with("version:") {
+            continue;
+        }
+        let attrs = lines
+            .iter()
+            .filter(|(_, l)| !l.to_lowercase().starts_with("version:"))
+            .map(|(n, l)| parse_attribute(l).map_err(|e| format!("{} (line {})", e, n)))
+            .collect::<Result<Vec<Attribute>, String>>();
+        match attrs.and_then(|a| record_entry(&a)) {
+            Ok(e) => entries.push((start, e)),
+            Err(message) => errors.push(RowError {
+                line: start,
+                message,
+            }),
+        }
+    }
+    (entries, errors)
+}
+
+/// Returns true if a value has to be base64 encoded: it is not ASCII,
+/// contains line breaks or NUL, or starts with a space, colon or `<`, or
+/// ends with a space.
+fn needs_base64(s: &str) -> bool {
+    !s.is_ascii()
+        || s.contains(['\n', '\r', '\0'])
+        || s.starts_with([' ', ':', '<'])
+        || s.ends_with(' ')
+}
+
+/// Formats an attribute line, folded at 76 characters.
+fn attribute(name: &str, value: &str) -> String {
+    let line = match needs_base64(value) {
+        true => format!("{}:: {}", name, base64::encode(value.as_bytes())),
+        false => format!("{}: {}", name, value),
+    };
+    // lines are ASCII here, so they can be split at any byte
+    let mut result = String::with_capacity(line.len() + 4);
+    let mut rest = line.as_str();
+    let mut width = 76;
+    while rest.len() > width {
+        result.push_str(&rest[..width]);
+        result.push_str("\n ");
+        rest = &rest[width..];
+        width = 75;
+    }
+    result.push_str(rest);
+    result.push('\n');
+    result
+}
+
+/// Escapes a value for use in a DN as described by RFC 4514.
+fn escape_dn(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let last = s.chars().count().saturating_sub(1);
+    for (i, c) in s.chars().enumerate() {
+        let special = ",+\"\\<>;=".contains(c)
+            || (i == 0 && (c == '#' || c == ' '))
+            || (i == last && c == ' ');
+        if special {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// Writes entries as inetOrgPerson records below `base_dn`. Records are
+/// named by their common name, with the entry ID added where names repeat.
+pub fn write_entries(entries: &[Entry], base_dn: &str) -> String {
+    let mut result = "version: 1\n".to_string();
+    for e in entries.iter() {
+        let cn = e.get(Field::Name);
+        let repeated = entries.iter().filter(|x| x.get(Field::Name) == cn).count() > 1;
+        let mut rdn = format!("cn={}", escape_dn(&cn));
+        if repeated {
+            rdn.push_str(format!("+uid={}", e.id).as_str());
+        }
+        let dn = match base_dn.is_empty() {
+            true => rdn,
+            false => format!("{},{}", rdn, base_dn),
+        };
+        result.push('\n');
+        result.push_str(attribute("dn", &dn).as_str());
+        for class in ["top", "person", "organizationalPerson", "inetOrgPerson"] {
+            result.push_str(attribute("objectClass", class).as_str());
+        }
+        result.push_str(attribute("cn", &cn).as_str());
+        // person requires a surname
+        let sn = match e.last_name.is_empty() {
+            true => &cn,
+            false => &e.last_name,
+        };
+        result.push_str(attribute("sn", sn).as_str());
+        let uid = match (repeated, e.uid.is_empty()) {
+            (true, _) => e.id.to_string(),
+            (false, false) => e.uid.clone(),
+            (false, true) => "".to_string(),
+        };
+        let address = e
+            .address
+            .replace('\\', "\\5C")
+            .replace('$', "\\24")
+            .replace(", ", "$");
+        let values = [
+            ("givenName", &e.first_name),
+            ("telephoneNumber", &e.phone),
+            ("mail", &e.e_mail),
+            ("postalAddress", &address),
+            ("o", &e.organization),
+            ("title", &e.title),
+            ("description", &e.note),
+            ("uid", &uid),
+        ];
+        for (name, value) in values.iter().filter(|(_, v)| !v.is_empty()) {
+            result.push_str(attribute(name, value).as_str());
+        }
+        let extra = PHONE_ATTRIBUTES
+            .iter()
+            .map(|(_, name)| (*name, *name))
+            .chain([("mail", "email")]);
+        for (name, key) in extra {
+            let values = e
+                .custom
+                .get(key)
+                .map(|v| v.split(','))
+                .into_iter()
+                .flatten();
+            for value in values.map(|v| v.trim()).filter(|v| !v.is_empty()) {
+                result.push_str(attribute(name, value).as_str());
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn read_records() {
+        let text = "version: 1\n\
+            \n\
+            # first person\n\
+            dn: cn=John Smith,ou=people,dc=example,dc=com\n\
+            objectClass: inetOrgPerson\n\
+            cn: John Smith\n\
+            cn: Johnny\n\
+            sn: Smith\n\
+            telephoneNumber: +1 555 123 4567\n\
+            telephoneNumber: +1 555 000 1111\n\
+            mail: john@example.com\n\
+            mail: js@example.com\n\
+            postalAddress: Main street 1$Spring\n\
+            \x20field\n\
+            o: ACME\n\
+            title: Engineer\n\
+            \n\
+            dn:: Y249Wm/DqyBKb25lcw==\n\
+            cn:: Wm/DqyBKb25lcw==\n\
+            sn: Jones\n\
+            mobile: +44 20 7946 0018\n\
+            \n\
+            dn: cn=Nobody\n\
+            mail: nobody@example.com\n\
+            \n\
+            dn: cn=Peter\n\
+            changetype: delete\n";
+        let (entries, errors) = read_entries(text);
+        assert_eq!(2, entries.len());
+        let (line, e) = &entries[0];
+        assert_eq!(4, *line);
+        assert_eq!("John", e.first_name);
+        assert_eq!("Smith", e.last_name);
+        assert_eq!("+1 555 123 4567", e.phone);
+        assert_eq!("john@example.com", e.e_mail);
+        assert_eq!(
+            Some(&"+1 555 000 1111".to_string()),
+            e.custom.get("telephoneNumber")
+        );
+        assert_eq!(Some(&"js@example.com".to_string()), e.custom.get("email"));
+        assert_eq!("Main street 1, Springfield", e.address);
+        assert_eq!("ACME", e.organization);
+        assert_eq!("Engineer", e.title);
+        let (line, e) = &entries[1];
+        assert_eq!(18, *line);
+        assert_eq!("Zoë", e.first_name);
+        assert_eq!("Jones", e.last_name);
+        assert_eq!("+44 20 7946 0018", e.phone);
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            vec![
+                "line 23: record has no name",
+                "line 26: change records (delete) are not supported"
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let entries = [
+            Entry {
+                id: 1,
+                first_name: "Zoë".to_string(),
+                last_name: "Smith, Jr.".to_string(),
+                address: "Main street 1, Springfield".to_string(),
+                note: "First line\nsecond line".to_string(),
+                ..Default::default()
+            },
+            Entry {
+                id: 2,
+                first_name: "John".to_string(),
+                last_name: "Smith".to_string(),
+                phone: "+1 555 123 4567".to_string(),
+                e_mail: "john@example.com".to_string(),
+                custom: BTreeMap::from([
+                    ("mobile".to_string(), "+1 555 000 1111".to_string()),
+                    (
+                        "email".to_string(),
+                        "js@example.com, j@example.org".to_string(),
+                    ),
+                ]),
+                ..Default::default()
+            },
+            Entry {
+                id: 3,
+                first_name: "John".to_string(),
+                last_name: "Smith".to_string(),
+                ..Default::default()
+            },
+        ];
+        let text = write_entries(&entries, "ou=people,dc=example,dc=com");
+        assert!(text
+            .contains("dn:: Y249Wm/DqyBTbWl0aFwsIEpyLixvdT1wZW9wbGUsZGM9ZXhhbXBsZSxkYz1jb20=\n"));
+        assert!(text.contains("dn: cn=John Smith+uid=2,ou=people,dc=example,dc=com\n"));
+        assert!(text.contains("postalAddress: Main street 1$Springfield\n"));
+        assert!(text.contains(
+            "mail: john@example.com\nuid: 2\nmobile: +1 555 000 1111\nmail: js@example.com\nmail: j@example.org\n"
+        ));
+        let (read, errors) = read_entries(&text);
+        assert!(errors.is_empty());
+        assert_eq!(3, read.len());
+        assert_eq!("Zoë", read[0].1.first_name);
+        assert_eq!("Smith, Jr.", read[0].1.last_name);
+        assert_eq!(entries[0].address, read[0].1.address);
+        assert_eq!(entries[0].note, read[0].1.note);
+        assert_eq!("+1 555 123 4567", read[1].1.phone);
+        assert_eq!(entries[1].custom, read[1].1.custom);
+    }
+}
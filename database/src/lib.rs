@@ -1,9 +1,47 @@
+pub mod abook;
+pub mod audit;
+mod backup;
+mod base64;
+mod crypt;
+pub mod csv;
 mod db;
+mod duplicates;
 mod entry;
+mod fuzzy;
+mod import;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod ldif;
+mod meta;
+pub mod mutt;
+mod normalize;
+mod phone;
+mod phonetic;
+mod profile;
+mod query;
+mod redact;
+pub mod snapshot;
+mod storage;
+mod time;
+mod validation;
+pub mod vcard;
 
-pub use db::FileDB;
+pub use crypt::{is_encrypted, Key};
 pub use db::DB;
+pub use db::{FileDB, Subject};
+pub use duplicates::{find_duplicates, merge, Duplicate};
 pub use entry::Entry;
+pub use fuzzy::Match;
+pub use import::{decode, Encoding, RowError};
+pub use meta::Metadata;
+pub use normalize::MatchOptions;
+pub use phone::{PhoneDigits, PhoneFormat, PhoneMatch, PhoneNumber, Region};
+pub use profile::Profile;
+pub use query::{Expr, Field, Query};
+pub use redact::Redaction;
+pub use storage::Storage;
+pub use time::DateTime;
+pub use validation::{Conflict, Rules, Violation, Violations};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,55 @@
+/// Settings of a phonebook, stored as `#key=value` lines at the top of the
+/// database file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    values: Vec<(String, String)>,
+}
+
+impl Metadata {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.values.iter_mut().find(|(k, _)| k == key) {
+            Some(x) => x.1 = value.to_string(),
+            None => self.values.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.values.retain(|(k, _)| k != key);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Parses a header line, returning None for lines that are not metadata.
+    pub fn parse_line(line: &str) -> Option<(String, String)> {
+        let (key, value) = line.strip_prefix('#')?.split_once('=')?;
+        Some((key.trim().to_string(), value.trim().to_string()))
+    }
+
+    /// Collects metadata from the header lines of a file.
+    pub fn from_lines<'a, I>(lines: I) -> Metadata
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let mut meta = Metadata::default();
+        for (k, v) in lines.filter_map(Metadata::parse_line) {
+            meta.set(&k, &v);
+        }
+        meta
+    }
+
+    pub fn to_lines(&self) -> Vec<String> {
+        self.values
+            .iter()
+            .map(|(k, v)| format!("#{}={}", k, v))
+            .collect()
+    }
+}
@@ -0,0 +1,296 @@
+use crate::entry::Entry;
+use crate::import::RowError;
+use crate::query::Field;
+
+/// Joins lines continued with a trailing backslash. Returns the lines with
+/// the line they start on.
+fn join_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut continued = false;
+    for (i, line) in text.lines().enumerate() {
+        let (line, next) = match line.strip_suffix('\\') {
+            Some(l) => (l, true),
+            None => (line, false),
+        };
+        match (continued, lines.last_mut()) {
+            (true, Some(last)) => last.1.push_str(line),
+            _ => lines.push((i + 1, line.to_string())),
+        }
+        continued = next;
+    }
+    lines
+}
+
+/// Takes the next word, which may be quoted, and returns it with the rest.
+fn next_word(s: &str) -> (String, &str) {
+    let s = s.trim_start();
+    let mut word = String::new();
+    let mut quote: Option<char> = None;
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => {
+                if let Some((_, x)) = chars.next() {
+                    word.push(x);
+                }
+            }
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (c, None) if c.is_whitespace() => return (word, &s[i..]),
+            _ => word.push(c),
+        }
+    }
+    (word, "")
+}
+
+/// Splits at `separator` outside of quotes, angle brackets and comments.
+fn split_unquoted(s: &str, separator: char) -> Vec<&str> {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => quoted = !quoted,
+            '<' | '(' if !quoted => depth += 1,
+            '>' | ')' if !quoted && depth > 0 => depth -= 1,
+            c if c == separator && !quoted && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Splits an address such as `John Smith <john@example.com>`,
+/// `john@example.com (John Smith)` or `john@example.com` into name and
+/// e-mail.
+fn parse_address(s: &str) -> (String, String) {
+    let s = s.trim();
+    let unquote = |n: &str| {
+        let n = n.trim();
+        match n.strip_prefix('"').and_then(|n| n.strip_suffix('"')) {
+            Some(q) => q.replace("\\\"", "\"").replace("\\\\", "\\"),
+            None => n.to_string(),
+        }
+    };
+    if let (Some(open), true) = (s.rfind('<'), s.ends_with('>')) {
+        return (
+            unquote(&s[..open]),
+            s[open + 1..s.len() - 1].trim().to_string(),
+        );
+    }
+    if let (Some(open), true) = (s.find('('), s.ends_with(')')) {
+        return (
+            s[open + 1..s.len() - 1].trim().to_string(),
+            s[..open].trim().to_string(),
+        );
+    }
+    (String::new(), s.to_string())
+}
+
+fn alias_entry(line: &str) -> Result<Entry, String> {
+    let (command, mut rest) = next_word(line);
+    if command != "alias" {
+        return Err(format!("{} is not an alias", command));
+    }
+    let mut e = Entry::default();
+    let key = loop {
+        let (word, r) = next_word(rest);
+        rest = r;
+        match word.as_str() {
+            "-group" => {
+                let (group, r) = next_word(rest);
+                rest = r;
+                e.tags.push(group);
+            }
+            "" => return Err("alias has no name".to_string()),
+            _ => break word,
+        }
+    };
+    // a comment after the addresses describes the alias
+    let parts = split_unquoted(rest, '#');
+    let addresses: Vec<&str> = split_unquoted(parts[0], ',')
+        .into_iter()
+        .map(|a| a.trim())
+        .filter(|a| !a.is_empty())
+        .collect();
+    if addresses.is_empty() {
+        return Err(format!("alias {} has no address", key));
+    }
+    let (name, e_mail) = parse_address(addresses[0]);
+    match name.is_empty() {
+        true => e.first_name = key.clone(),
+        false => e.set(Field::Name, &name),
+    }
+    e.e_mail = e_mail;
+    if addresses.len() > 1 {
+        e.custom
+            .insert("email".to_string(), addresses[1..].join(", "));
+    }
+    if parts.len() > 1 {
+        e.note = parts[1..].join("#").trim().to_string();
+    }
+    e.custom.insert("alias".to_string(), key);
+    Ok(e)
+}
+
+/// Reads a mutt alias file. The alias is kept as custom field `alias`, the
+/// groups as tags and further addresses as custom field `email`. Lines that
+/// can not be read are reported without stopping the import.
+pub fn read_entries(text: &str) -> (Vec<(usize, Entry)>, Vec<RowError>) {
+    let mut entries: Vec<(usize, Entry)> = Vec::new();
+    let mut errors: Vec<RowError> = Vec::new();
+    for (line, content) in join_lines(text) {
+        let content = content.trim();
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+        match alias_entry(content) {
+            Ok(e) => entries.push((line, e)),
+            Err(message) => errors.push(RowError { line, message }),
+        }
+    }
+    (entries, errors)
+}
+
+/// Quotes a name that contains characters with a meaning in addresses.
+fn quote_name(name: &str) -> String {
+    match name.contains(|c: char| "()<>[]:;@\\,.\"#'".contains(c)) {
+        true => format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => name.to_string(),
+    }
+}
+
+/// Makes an alias of a name, e.g. `john-smith` of John Smith.
+fn make_alias(name: &str) -> String {
+    let words: Vec<String> = name
+        .split_whitespace()
+        .map(|w| {
+            w.chars()
+                .filter(|c| c.is_alphanumeric() || "-_.".contains(*c))
+                .collect::<String>()
+        })
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+    words.join("-")
+}
+
+/// Writes entries as mutt aliases, using the custom field `alias` or else an
+/// alias made of the name, with the entry ID added where aliases repeat.
+/// Entries without an e-mail address are left out.
+pub fn write_entries(entries: &[Entry]) -> String {
+    let alias = |e: &Entry| match e.custom.get("alias") {
+        Some(a) if !a.trim().is_empty() => a.trim().to_string(),
+        _ => make_alias(&e.get(Field::Name)),
+    };
+    let entries: Vec<&Entry> = entries
+        .iter()
+        .filter(|e| !e.e_mail.trim().is_empty())
+        .collect();
+    let mut result = String::new();
+    for e in entries.iter() {
+        let mut key = alias(e);
+        if key.is_empty() || entries.iter().filter(|x| alias(x) == key).count() > 1 {
+            key = format!("{}{}", key, e.id);
+        }
+        let mut line = "alias".to_string();
+        for tag in e.tags.iter() {
+            line.push_str(format!(" -group {}", make_alias(tag)).as_str());
+        }
+        line.push_str(format!(" {} ", key).as_str());
+        let name = e.get(Field::Name);
+        if !name.is_empty() {
+            line.push_str(format!("{} ", quote_name(&name)).as_str());
+        }
+        line.push_str(format!("<{}>", e.e_mail.trim()).as_str());
+        if let Some(rest) = e.custom.get("email") {
+            line.push_str(format!(", {}", rest).as_str());
+        }
+        if !e.note.trim().is_empty() {
+            line.push_str(format!(" # {}", e.note.replace(['\n', '\r'], " ").trim()).as_str());
+        }
+        result.push_str(line.as_str());
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_aliases() {
+        let text = "# aliases\n\
+            alias -group friends john John Smith <john@example.com> # from work\n\
+            alias mary \"Mary J. Jones\" <mary@example.com>, m@example.com\n\
+            alias peter peter@example.com (Peter \\\n\
+            Parker)\n\
+            alias bob\n\
+            set sort = date\n";
+        let (entries, errors) = read_entries(text);
+        assert_eq!(3, entries.len());
+        let (line, e) = &entries[0];
+        assert_eq!(2, *line);
+        assert_eq!("John", e.first_name);
+        assert_eq!("Smith", e.last_name);
+        assert_eq!("john@example.com", e.e_mail);
+        assert_eq!(vec!["friends"], e.tags);
+        assert_eq!("from work", e.note);
+        assert_eq!(Some(&"john".to_string()), e.custom.get("alias"));
+        let e = &entries[1].1;
+        assert_eq!("J. Jones", e.last_name);
+        assert_eq!("mary@example.com", e.e_mail);
+        assert_eq!(Some(&"m@example.com".to_string()), e.custom.get("email"));
+        let e = &entries[2].1;
+        assert_eq!("Peter", e.first_name);
+        assert_eq!("Parker", e.last_name);
+        assert_eq!("peter@example.com", e.e_mail);
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            vec![
+                "line 6: alias bob has no address",
+                "line 7: set is not an alias"
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn write_aliases() {
+        let mut john = Entry {
+            id: 1,
+            first_name: "John".to_string(),
+            last_name: "Smith, Jr.".to_string(),
+            e_mail: "john@example.com".to_string(),
+            tags: vec!["work".to_string()],
+            ..Default::default()
+        };
+        let mut other = john.clone();
+        other.id = 2;
+        other.last_name = "Smith,  Jr.".to_string();
+        let nobody = Entry {
+            first_name: "Nobody".to_string(),
+            ..Default::default()
+        };
+        let text = write_entries(&[john.clone(), other, nobody]);
+        assert_eq!(
+            "alias -group work john-smith-jr.1 \"John Smith, Jr.\" <john@example.com>\n\
+            alias -group work john-smith-jr.2 \"John Smith,  Jr.\" <john@example.com>\n",
+            text
+        );
+        john.custom.insert("alias".to_string(), "js".to_string());
+        let (read, errors) = read_entries(&write_entries(&[john.clone()]));
+        assert!(errors.is_empty());
+        assert_eq!(john.get(Field::Name), read[0].1.get(Field::Name));
+        assert_eq!(Some(&"js".to_string()), read[0].1.custom.get("alias"));
+    }
+}
@@ -0,0 +1,190 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Controls how text is compared when searching.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MatchOptions {
+    /// Compare text using Unicode case folding.
+    pub ignore_case: bool,
+    /// Strip diacritics before comparing, so "Jose" matches "José".
+    pub ignore_accents: bool,
+}
+
+impl Default for MatchOptions {
+    fn default() -> MatchOptions {
+        MatchOptions {
+            ignore_case: true,
+            ignore_accents: true,
+        }
+    }
+}
+
+impl MatchOptions {
+    /// Byte-exact comparison apart from canonical composition.
+    pub fn exact() -> MatchOptions {
+        MatchOptions {
+            ignore_case: false,
+            ignore_accents: false,
+        }
+    }
+}
+
+/// Brings text into the form used for comparison.
+///
+/// Text is always converted to NFC so that precomposed and decomposed
+/// characters compare equal. Depending on the options, diacritics of Latin,
+/// Greek and Cyrillic letters are then removed and the result is case folded.
+/// Marks of other scripts, e.g. the dakuten of が or Devanagari vowel signs,
+/// change the letter and are kept.
+pub fn normalize(s: &str, options: MatchOptions) -> String {
+    let mut result = String::with_capacity(s.len());
+    if options.ignore_accents {
+        // whether marks belong to a letter whose accents are removed
+        let mut strip_marks = false;
+        for c in s.nfc() {
+            if is_combining_mark(c) {
+                if !strip_marks {
+                    result.push(c);
+                }
+                continue;
+            }
+            strip_marks = has_accents(c);
+            for d in c.to_string().nfkd() {
+                match strip_marks && is_combining_mark(d) {
+                    true => (),
+                    false => result.push(strip_stroke(d)),
+                }
+            }
+        }
+    } else {
+        result.extend(s.nfc());
+    }
+    if options.ignore_case {
+        result = fold_case(&result);
+    }
+    if options.ignore_accents {
+        // recompose characters whose marks were not removed, e.g. Hangul
+        result = result.nfc().collect();
+    }
+    result
+}
+
+/// Returns true if `haystack` contains `needle` after normalizing both.
+pub fn contains(haystack: &str, needle: &str, options: MatchOptions) -> bool {
+    normalize(haystack, options).contains(normalize(needle, options).as_str())
+}
+
+/// Returns true if both strings are equal after normalizing them.
+pub fn equals(a: &str, b: &str, options: MatchOptions) -> bool {
+    normalize(a, options) == normalize(b, options)
+}
+
+fn fold_case(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            // full case folding cases that to_lowercase does not cover
+            'ß' | 'ẞ' => result.push_str("ss"),
+            'ς' => result.push('σ'),
+            _ => result.extend(c.to_lowercase()),
+        }
+    }
+    result
+}
+
+/// Returns true for characters of the Latin, Greek and Cyrillic scripts, whose
+/// diacritics are ignored when searching.
+fn has_accents(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0}'..='\u{24f}'
+            | '\u{1e00}'..='\u{1eff}'
+            | '\u{2c60}'..='\u{2c7f}'
+            | '\u{a720}'..='\u{a7ff}'
+            | '\u{ff01}'..='\u{ff5e}'
+            | '\u{370}'..='\u{3ff}'
+            | '\u{1f00}'..='\u{1fff}'
+            | '\u{400}'..='\u{52f}'
+            | '\u{1c80}'..='\u{1c8f}'
+            | '\u{a640}'..='\u{a69f}'
+    )
+}
+
+/// Maps letters whose diacritic is part of the glyph, and therefore has no
+/// canonical decomposition, to their base letter.
+fn strip_stroke(c: char) -> char {
+    match c {
+        'ø' => 'o',
+        'Ø' => 'O',
+        'ł' => 'l',
+        'Ł' => 'L',
+        'đ' => 'd',
+        'Đ' => 'D',
+        'ħ' => 'h',
+        'Ħ' => 'H',
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latin_case_and_accents() {
+        let o = MatchOptions::default();
+        assert!(equals("José", "jose", o));
+        assert!(equals("Straße", "STRASSE", o));
+        assert!(equals("Łódź", "lodz", o));
+        assert!(contains("Ana Müller", "muller", o));
+    }
+
+    #[test]
+    fn composed_and_decomposed_forms_are_equal() {
+        assert!(equals("Jos\u{e9}", "Jose\u{301}", MatchOptions::exact()));
+        assert!(!equals("José", "Jose", MatchOptions::exact()));
+        assert!(!equals("José", "josé", MatchOptions::exact()));
+    }
+
+    #[test]
+    fn options_are_independent() {
+        let case_only = MatchOptions {
+            ignore_case: true,
+            ignore_accents: false,
+        };
+        assert!(equals("JOSÉ", "josé", case_only));
+        assert!(!equals("JOSÉ", "jose", case_only));
+        let accents_only = MatchOptions {
+            ignore_case: false,
+            ignore_accents: true,
+        };
+        assert!(equals("José", "Jose", accents_only));
+        assert!(!equals("José", "jose", accents_only));
+    }
+
+    #[test]
+    fn greek() {
+        let o = MatchOptions::default();
+        assert!(equals("Ελλάδα", "ελλαδα", o));
+        assert!(equals("Οδυσσέας", "ΟΔΥΣΣΕΑΣ", o));
+        assert!(contains("Νίκος Παπαδόπουλος", "παπαδοπουλος", o));
+    }
+
+    #[test]
+    fn cyrillic() {
+        let o = MatchOptions::default();
+        assert!(equals("Ёлкин", "елкин", o));
+        assert!(contains("Иван Петров", "петров", o));
+    }
+
+    #[test]
+    fn scripts_without_case() {
+        let o = MatchOptions::default();
+        assert!(contains("山田 太郎", "太郎", o));
+        assert!(!equals("が", "か", o));
+        assert!(!contains("किताब", "कताब", o));
+        assert!(!equals("عَلِي", "علي", o));
+        assert!(equals("ｶﾞ", "ガ", o));
+        assert!(contains("محمد علي", "علي", o));
+    }
+}
@@ -0,0 +1,500 @@
+/// Minimum number of digits a number needs for a suffix match, so that a
+/// short extension does not match every number ending with it.
+const MIN_SUFFIX_DIGITS: usize = 7;
+
+/// How well a stored phone number matches a looked up one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PhoneMatch {
+    /// One number ends with the other, e.g. a number stored without area
+    /// code.
+    Suffix,
+    /// Both numbers are the same once formatting, international and trunk
+    /// prefixes are removed.
+    Exact,
+}
+
+/// Phone number reduced to its digits.
+#[derive(Debug, PartialEq)]
+pub struct PhoneDigits {
+    /// Country calling code, known only if the number was written in
+    /// international format (with a leading `+` or `00`).
+    pub country_code: Option<String>,
+    /// Significant digits of the number, without trunk prefix.
+    pub national: String,
+}
+
+impl PhoneDigits {
+    /// Extracts the digits of a phone number written in any common format,
+    /// e.g. "+1 (555) 123-4567", "0044 20 7946 0018" or "020 7946 0018".
+    /// Returns None if the text contains no digits.
+    pub fn parse(s: &str) -> Option<PhoneDigits> {
+        let trimmed = s.trim_start();
+        let digits: String = trimmed.chars().filter(|c| c.is_ascii_digit()).collect();
+        if digits.is_empty() {
+            return None;
+        }
+        let international = if trimmed.starts_with('+') {
+            Some(digits.as_str())
+        } else {
+            digits.strip_prefix("00")
+        };
+        let (country_code, national) = match international {
+            Some(d) => {
+                let (cc, rest) = split_country_code(d);
+                (Some(cc.to_string()), rest)
+            }
+            None => (None, digits.as_str()),
+        };
+        Some(PhoneDigits {
+            country_code,
+            // trunk prefixes like the 0 in "020 7946 0018" are not dialled
+            // from abroad; Italian numbers keep it, so strip it everywhere
+            national: national.trim_start_matches('0').to_string(),
+        })
+    }
+
+    /// Compares two numbers. Country codes are only compared when both
+    /// numbers have one.
+    pub fn compare(&self, other: &PhoneDigits) -> Option<PhoneMatch> {
+        if let (Some(a), Some(b)) = (&self.country_code, &other.country_code) {
+            if a != b {
+                return None;
+            }
+        }
+        if self.national.is_empty() || other.national.is_empty() {
+            return None;
+        }
+        if self.national == other.national {
+            return Some(PhoneMatch::Exact);
+        }
+        let (short, long) = if self.national.len() < other.national.len() {
+            (&self.national, &other.national)
+        } else {
+            (&other.national, &self.national)
+        };
+        if short.len() >= MIN_SUFFIX_DIGITS && long.ends_with(short.as_str()) {
+            return Some(PhoneMatch::Suffix);
+        }
+        None
+    }
+}
+
+// Two digit country calling codes. Calling codes are prefix free: codes
+// starting with 1 or 7 have one digit, codes listed here two, all others
+// three.
+const TWO_DIGIT_CODES: [&str; 44] = [
+    "20", "27", "30", "31", "32", "33", "34", "36", "39", "40", "41", "43", "44", "45", "46", "47",
+    "48", "49", "51", "52", "53", "54", "55", "56", "57", "58", "60", "61", "62", "63", "64", "65",
+    "66", "81", "82", "84", "86", "90", "91", "92", "93", "94", "95", "98",
+];
+
+/// Numbering plan of a region, used to interpret numbers written without
+/// country code.
+#[derive(Debug, PartialEq)]
+pub struct Region {
+    /// ISO 3166-1 alpha-2 code.
+    pub code: &'static str,
+    pub calling_code: &'static str,
+    /// Prefix dialled before national numbers, removed in international
+    /// format.
+    pub trunk_prefix: &'static str,
+    /// Range of lengths of the national significant number.
+    pub min_len: usize,
+    pub max_len: usize,
+}
+
+const fn region(
+    code: &'static str,
+    calling_code: &'static str,
+    trunk_prefix: &'static str,
+    min_len: usize,
+    max_len: usize,
+) -> Region {
+    Region {
+        code,
+        calling_code,
+        trunk_prefix,
+        min_len,
+        max_len,
+    }
+}
+
+// Regions sharing a calling code are listed after the one used to format
+// their numbers.
+const REGIONS: [Region; 46] = [
+    region("US", "1", "1", 10, 10),
+    region("CA", "1", "1", 10, 10),
+    region("RU", "7", "8", 10, 10),
+    region("KZ", "7", "8", 10, 10),
+    region("EG", "20", "0", 9, 10),
+    region("ZA", "27", "0", 9, 9),
+    region("GR", "30", "", 10, 10),
+    region("NL", "31", "0", 9, 9),
+    region("BE", "32", "0", 8, 9),
+    region("FR", "33", "0", 9, 9),
+    region("ES", "34", "", 9, 9),
+    region("HU", "36", "06", 8, 9),
+    // Italian numbers keep their leading 0 in international format
+    region("IT", "39", "", 6, 11),
+    region("RO", "40", "0", 9, 9),
+    region("CH", "41", "0", 9, 9),
+    region("AT", "43", "0", 4, 13),
+    region("GB", "44", "0", 7, 10),
+    region("DK", "45", "", 8, 8),
+    region("SE", "46", "0", 7, 10),
+    region("NO", "47", "", 8, 8),
+    region("PL", "48", "", 9, 9),
+    region("DE", "49", "0", 5, 13),
+    region("MX", "52", "", 10, 10),
+    region("AR", "54", "0", 10, 10),
+    region("BR", "55", "0", 10, 11),
+    region("AU", "61", "0", 9, 9),
+    region("NZ", "64", "0", 8, 10),
+    region("JP", "81", "0", 9, 10),
+    region("KR", "82", "0", 8, 10),
+    region("CN", "86", "0", 9, 11),
+    region("TR", "90", "0", 10, 10),
+    region("IN", "91", "0", 10, 10),
+    region("PT", "351", "", 9, 9),
+    region("IE", "353", "0", 7, 9),
+    region("FI", "358", "0", 5, 12),
+    region("BG", "359", "0", 7, 9),
+    region("UA", "380", "0", 9, 9),
+    region("RS", "381", "0", 6, 12),
+    region("ME", "382", "0", 8, 8),
+    region("HR", "385", "0", 8, 9),
+    region("SI", "386", "0", 8, 8),
+    region("BA", "387", "0", 8, 9),
+    region("MK", "389", "0", 8, 8),
+    region("CZ", "420", "", 9, 9),
+    region("SK", "421", "0", 9, 9),
+    region("IL", "972", "0", 8, 9),
+];
+
+// Limits for numbers of regions missing from the table.
+const MIN_NATIONAL_LEN: usize = 4;
+const MAX_E164_LEN: usize = 15;
+
+impl Region {
+    /// Finds a region by its ISO 3166-1 alpha-2 code, ignoring case.
+    pub fn find(code: &str) -> Option<&'static Region> {
+        REGIONS.iter().find(|r| r.code.eq_ignore_ascii_case(code))
+    }
+
+    fn by_calling_code(calling_code: &str) -> Option<&'static Region> {
+        REGIONS.iter().find(|r| r.calling_code == calling_code)
+    }
+}
+
+/// How phone numbers are presented.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PhoneFormat {
+    /// As the number was typed.
+    Original,
+    /// As dialled within the number's region, e.g. "020 7946 0018".
+    National,
+    /// With country code, e.g. "+44 20 7946 0018".
+    International,
+    /// Canonical form without formatting, e.g. "+442079460018".
+    E164,
+}
+
+impl PhoneFormat {
+    pub fn from(s: &str) -> Result<PhoneFormat, String> {
+        match s {
+            "original" => Ok(PhoneFormat::Original),
+            "national" => Ok(PhoneFormat::National),
+            "international" => Ok(PhoneFormat::International),
+            "e164" => Ok(PhoneFormat::E164),
+            _ => Err(format!("unknown phone format {}", s)),
+        }
+    }
+}
+
+/// Phone number split into country calling code and national significant
+/// number.
+#[derive(Debug, PartialEq)]
+pub struct PhoneNumber {
+    pub calling_code: String,
+    pub national: String,
+}
+
+impl PhoneNumber {
+    /// Parses a number typed by a user. Numbers in international format
+    /// (starting with `+` or `00`) are parsed on their own, others are
+    /// interpreted as numbers of `region`.
+    ///
+    /// Returns `Ok(None)` for a plausible national number when no region is
+    /// known, and an error for text that is not a phone number.
+    pub fn parse(s: &str, region: Option<&Region>) -> Result<Option<PhoneNumber>, String> {
+        let trimmed = s.trim();
+        for (i, c) in trimmed.chars().enumerate() {
+            match c {
+                '+' if i == 0 => (),
+                _ if c.is_ascii_digit() || c.is_whitespace() => (),
+                '-' | '(' | ')' | '.' | '/' => (),
+                _ => return Err(format!("phone number contains invalid character '{}'", c)),
+            }
+        }
+        let digits: String = trimmed.chars().filter(|c| c.is_ascii_digit()).collect();
+        if digits.is_empty() {
+            return Err("phone number has no digits".to_string());
+        }
+        let international = if trimmed.starts_with('+') {
+            Some(digits.as_str())
+        } else {
+            digits.strip_prefix("00")
+        };
+        let (calling_code, national, region) = match (international, region) {
+            (Some(d), _) => {
+                let (cc, rest) = split_country_code(d);
+                (cc, rest, Region::by_calling_code(cc))
+            }
+            (None, Some(r)) => (r.calling_code, digits.as_str(), Some(r)),
+            (None, None) => {
+                if digits.len() < MIN_NATIONAL_LEN || digits.len() > MAX_E164_LEN {
+                    return Err(format!(
+                        "phone number must have {} to {} digits",
+                        MIN_NATIONAL_LEN, MAX_E164_LEN
+                    ));
+                }
+                return Ok(None);
+            }
+        };
+        // national numbers often carry the trunk prefix, international ones
+        // sometimes as in "+44 (0)20"
+        let national = match region {
+            Some(r) if !r.trunk_prefix.is_empty() && national.len() > r.min_len => {
+                national.strip_prefix(r.trunk_prefix).unwrap_or(national)
+            }
+            _ => national,
+        };
+        let (min_len, max_len) = match region {
+            Some(r) => (r.min_len, r.max_len),
+            None => (MIN_NATIONAL_LEN, MAX_E164_LEN - calling_code.len()),
+        };
+        if national.len() < min_len || national.len() > max_len {
+            return Err(match region {
+                Some(r) if min_len == max_len => {
+                    format!("phone numbers in {} must have {} digits", r.code, min_len)
+                }
+                Some(r) => format!(
+                    "phone numbers in {} must have {} to {} digits",
+                    r.code, min_len, max_len
+                ),
+                None => format!(
+                    "phone numbers with country code {} must have {} to {} digits",
+                    calling_code, min_len, max_len
+                ),
+            });
+        }
+        Ok(Some(PhoneNumber {
+            calling_code: calling_code.to_string(),
+            national: national.to_string(),
+        }))
+    }
+
+    /// Parses a number stored in E.164 format.
+    pub fn from_e164(s: &str) -> Option<PhoneNumber> {
+        let digits = s.strip_prefix('+')?;
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let (cc, national) = split_country_code(digits);
+        Some(PhoneNumber {
+            calling_code: cc.to_string(),
+            national: national.to_string(),
+        })
+    }
+
+    pub fn e164(&self) -> String {
+        format!("+{}{}", self.calling_code, self.national)
+    }
+
+    pub fn format(&self, format: PhoneFormat) -> String {
+        match format {
+            PhoneFormat::Original | PhoneFormat::E164 => self.e164(),
+            PhoneFormat::International => format!("+{} {}", self.calling_code, group_digits(self)),
+            PhoneFormat::National => {
+                let trunk = match Region::by_calling_code(&self.calling_code) {
+                    // the North American trunk prefix is not written
+                    Some(r) if r.calling_code != "1" => r.trunk_prefix,
+                    _ => "",
+                };
+                format!("{}{}", trunk, group_digits(self))
+            }
+        }
+    }
+}
+
+/// Sizes of the digit groups of a national number in the numbering plans
+/// whose grouping follows from the number alone, empty for others.
+fn group_sizes(number: &PhoneNumber) -> &'static [usize] {
+    let national = number.national.as_bytes();
+    match (number.calling_code.as_str(), national.len()) {
+        ("1", 10) => &[3, 3, 4],
+        ("33", 9) => &[1, 2, 2, 2, 2],
+        // London, Southampton, Cardiff etc. have two digit area codes,
+        // Leeds (113) or Birmingham (121) three, most others four
+        ("44", 10) => match (national[0], national[1], national[2]) {
+            (b'2', _, _) => &[2, 4, 4],
+            (b'1', b'1', _) | (b'1', _, b'1') => &[3, 3, 4],
+            _ => &[4, 6],
+        },
+        _ => &[],
+    }
+}
+
+/// Groups the digits of the national number the way they are written in its
+/// region, e.g. "20 7946 0018". Numbers of other plans are not grouped,
+/// since their area codes differ in length.
+fn group_digits(number: &PhoneNumber) -> String {
+    let mut groups: Vec<&str> = Vec::new();
+    let mut rest = number.national.as_str();
+    for size in group_sizes(number) {
+        let (group, r) = rest.split_at(*size);
+        groups.push(group);
+        rest = r;
+    }
+    if !rest.is_empty() {
+        groups.push(rest);
+    }
+    groups.join(" ")
+}
+
+/// Splits digits of an international number into country code and the rest.
+pub fn split_country_code(digits: &str) -> (&str, &str) {
+    let len = match digits.chars().next() {
+        Some('1') | Some('7') => 1,
+        _ if digits.len() >= 2 && TWO_DIGIT_CODES.contains(&&digits[..2]) => 2,
+        _ => 3,
+    };
+    digits.split_at(len.min(digits.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare(a: &str, b: &str) -> Option<PhoneMatch> {
+        PhoneDigits::parse(a)
+            .unwrap()
+            .compare(&PhoneDigits::parse(b).unwrap())
+    }
+
+    #[test]
+    fn parse_formats() {
+        let expected = PhoneDigits {
+            country_code: Some("44".to_string()),
+            national: "2079460018".to_string(),
+        };
+        assert_eq!(Some(expected), PhoneDigits::parse("+44 (0)20 7946 0018"));
+        assert_eq!(
+            Some("44".to_string()),
+            PhoneDigits::parse("0044 20 7946 0018")
+                .unwrap()
+                .country_code
+        );
+        let national = PhoneDigits::parse("020 7946-0018").unwrap();
+        assert_eq!(None, national.country_code);
+        assert_eq!("2079460018", national.national);
+        assert_eq!(None, PhoneDigits::parse("n/a"));
+    }
+
+    #[test]
+    fn country_codes() {
+        assert_eq!(("1", "5551234"), split_country_code("15551234"));
+        assert_eq!(("381", "641234"), split_country_code("381641234"));
+        assert_eq!(("49", "301234"), split_country_code("49301234"));
+    }
+
+    #[test]
+    fn exact_matches() {
+        assert_eq!(
+            Some(PhoneMatch::Exact),
+            compare("+1 (555) 123-4", "5551234")
+        );
+        assert_eq!(
+            Some(PhoneMatch::Exact),
+            compare("+44 20 7946 0018", "020 7946 0018")
+        );
+        assert_eq!(
+            Some(PhoneMatch::Exact),
+            compare("+39 06 1234 5678", "06 1234 5678")
+        );
+        assert_eq!(None, compare("+44 20 7946 0018", "+49 20 7946 0018"));
+    }
+
+    #[test]
+    fn parse_with_region() {
+        let gb = Region::find("gb");
+        let number = PhoneNumber::parse("020 7946 0018", gb).unwrap().unwrap();
+        assert_eq!("+442079460018", number.e164());
+        let number = PhoneNumber::parse("+44 (0)20 7946 0018", None)
+            .unwrap()
+            .unwrap();
+        assert_eq!("+442079460018", number.e164());
+        let number = PhoneNumber::parse("(555) 123-4567", Region::find("US"))
+            .unwrap()
+            .unwrap();
+        assert_eq!("+15551234567", number.e164());
+        let number = PhoneNumber::parse("06 1234 5678", Region::find("IT"))
+            .unwrap()
+            .unwrap();
+        assert_eq!("+390612345678", number.e164());
+        assert_eq!(None, PhoneNumber::parse("020 7946 0018", None).unwrap());
+    }
+
+    #[test]
+    fn reject_invalid_numbers() {
+        let us = Region::find("US");
+        assert_eq!(
+            Err("phone number contains invalid character 'a'".to_string()),
+            PhoneNumber::parse("555 abc", us)
+        );
+        assert_eq!(
+            Err("phone number has no digits".to_string()),
+            PhoneNumber::parse("--", us)
+        );
+        assert_eq!(
+            Err("phone numbers in US must have 10 digits".to_string()),
+            PhoneNumber::parse("123", us)
+        );
+        assert!(PhoneNumber::parse("12", None).is_err());
+        assert!(PhoneNumber::parse("+44 20 7946 0018 1234 5", None).is_err());
+    }
+
+    #[test]
+    fn format() {
+        let number = PhoneNumber::from_e164("+442079460018").unwrap();
+        assert_eq!(
+            "+44 20 7946 0018",
+            number.format(PhoneFormat::International)
+        );
+        assert_eq!("020 7946 0018", number.format(PhoneFormat::National));
+        let number = PhoneNumber::from_e164("+441132345678").unwrap();
+        assert_eq!("0113 234 5678", number.format(PhoneFormat::National));
+        let number = PhoneNumber::from_e164("+447700900123").unwrap();
+        assert_eq!("07700 900123", number.format(PhoneFormat::National));
+        let number = PhoneNumber::from_e164("+15551234567").unwrap();
+        assert_eq!("555 123 4567", number.format(PhoneFormat::National));
+        assert_eq!("+1 555 123 4567", number.format(PhoneFormat::International));
+        let number = PhoneNumber::from_e164("+33123456789").unwrap();
+        assert_eq!("01 23 45 67 89", number.format(PhoneFormat::National));
+        // area codes in Germany have two to five digits
+        let number = PhoneNumber::from_e164("+49301234567").unwrap();
+        assert_eq!("+49 301234567", number.format(PhoneFormat::International));
+        assert_eq!("0301234567", number.format(PhoneFormat::National));
+        assert_eq!(None, PhoneNumber::from_e164("5551234567"));
+    }
+
+    #[test]
+    fn suffix_matches() {
+        assert_eq!(
+            Some(PhoneMatch::Suffix),
+            compare("123 4567", "+1 555 123 4567")
+        );
+        assert_eq!(None, compare("4567", "+1 555 123 4567"));
+        assert_eq!(None, compare("1234568", "+1 555 123 4567"));
+    }
+}
@@ -0,0 +1,377 @@
+use crate::entry::Entry;
+use crate::normalize::{self, MatchOptions};
+use std::collections::HashMap;
+
+const MAX_CODE_LEN: usize = 4;
+
+/// Phonetic codes of a single word.
+///
+/// Like Double Metaphone, a word gets a primary and an alternate code, which
+/// differ when the word has more than one plausible pronunciation (e.g. the
+/// "sch" in "Schmidt"). Two words sound alike if any of their codes are equal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Codes {
+    pub primary: String,
+    pub alternate: String,
+}
+
+impl Codes {
+    pub fn sounds_like(&self, other: &Codes) -> bool {
+        self.primary == other.primary
+            || self.primary == other.alternate
+            || self.alternate == other.primary
+            || self.alternate == other.alternate
+    }
+}
+
+/// Phonetic codes of the first and last names of every entry, kept up to
+/// date by the database as entries are created, updated and deleted.
+#[derive(Default)]
+pub struct PhoneticIndex {
+    codes: HashMap<u16, Vec<Codes>>,
+}
+
+impl PhoneticIndex {
+    pub fn build(entries: &[Entry]) -> PhoneticIndex {
+        let mut index = PhoneticIndex::default();
+        for e in entries {
+            index.insert(e);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, e: &Entry) {
+        let mut codes = encode_words(&e.first_name);
+        codes.extend(encode_words(&e.last_name));
+        self.codes.insert(e.id, codes);
+    }
+
+    pub fn remove(&mut self, id: u16) {
+        self.codes.remove(&id);
+    }
+
+    /// Returns true if every word of the term sounds like one of the names
+    /// of the entry with the given ID.
+    pub fn matches(&self, id: u16, term: &[Codes]) -> bool {
+        match self.codes.get(&id) {
+            Some(names) => {
+                !term.is_empty() && term.iter().all(|t| names.iter().any(|n| n.sounds_like(t)))
+            }
+            None => false,
+        }
+    }
+}
+
+/// Encodes every word of the text, skipping words without letters.
+pub fn encode_words(s: &str) -> Vec<Codes> {
+    s.split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(encode)
+        .filter(|c| !c.primary.is_empty())
+        .collect()
+}
+
+/// Computes the phonetic codes of a word using a simplified variant of the
+/// Double Metaphone algorithm. Only the Latin alphabet is encoded; accents
+/// are stripped first and other characters are ignored.
+pub fn encode(word: &str) -> Codes {
+    let normalized = normalize::normalize(word, MatchOptions::default()).to_ascii_uppercase();
+    let w: Vec<char> = normalized
+        .chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .collect();
+    let mut e = Encoder {
+        w,
+        primary: String::new(),
+        alternate: String::new(),
+    };
+    e.run();
+    Codes {
+        primary: e.primary,
+        alternate: e.alternate,
+    }
+}
+
+struct Encoder {
+    w: Vec<char>,
+    primary: String,
+    alternate: String,
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'A' | 'E' | 'I' | 'O' | 'U' | 'Y')
+}
+
+impl Encoder {
+    fn at(&self, i: usize) -> char {
+        *self.w.get(i).unwrap_or(&'\0')
+    }
+
+    fn starts_with_at(&self, i: usize, s: &str) -> bool {
+        s.chars().enumerate().all(|(k, c)| self.at(i + k) == c)
+    }
+
+    fn add(&mut self, primary: &str, alternate: &str) {
+        self.primary.push_str(primary);
+        self.alternate.push_str(alternate);
+    }
+
+    fn done(&self) -> bool {
+        self.primary.len() >= MAX_CODE_LEN && self.alternate.len() >= MAX_CODE_LEN
+    }
+
+    fn run(&mut self) {
+        let len = self.w.len();
+        let mut i = 0;
+        // silent first letters
+        if ["GN", "KN", "PN", "WR", "PS"]
+            .iter()
+            .any(|p| self.starts_with_at(0, p))
+        {
+            i = 1;
+        }
+        if self.at(0) == 'X' {
+            // Xavier
+            self.add("S", "S");
+            i = 1;
+        }
+        while i < len && !self.done() {
+            let c = self.at(i);
+            let next = self.at(i + 1);
+            // number of characters consumed by this step
+            let mut step = 1;
+            match c {
+                _ if is_vowel(c) => {
+                    if i == 0 {
+                        self.add("A", "A");
+                    }
+                }
+                'B' => {
+                    // silent in "-mb" at the end, as in "Plumb"
+                    if !(i + 1 == len && i > 0 && self.at(i - 1) == 'M') {
+                        self.add("P", "P");
+                    }
+                }
+                'C' => {
+                    if self.starts_with_at(i, "CHR") || (i == 0 && self.starts_with_at(i, "CHAE")) {
+                        // Christopher, Chaeron
+                        self.add("K", "K");
+                        step = 2;
+                    } else if next == 'H' {
+                        self.add("X", "K");
+                        step = 2;
+                    } else if self.starts_with_at(i, "CIA") {
+                        self.add("X", "X");
+                        step = 3;
+                    } else if matches!(next, 'I' | 'E' | 'Y') {
+                        self.add("S", "S");
+                        step = 2;
+                    } else {
+                        self.add("K", "K");
+                        if matches!(next, 'C' | 'K' | 'Q') {
+                            step = 2;
+                        }
+                    }
+                }
+                'D' => {
+                    if next == 'G' && matches!(self.at(i + 2), 'I' | 'E' | 'Y') {
+                        // Edge
+                        self.add("J", "J");
+                        step = 3;
+                    } else {
+                        self.add("T", "T");
+                        if matches!(next, 'T' | 'D') {
+                            step = 2;
+                        }
+                    }
+                }
+                'G' => {
+                    if next == 'H' {
+                        if i > 0 && !is_vowel(self.at(i - 1)) {
+                            self.add("K", "K");
+                        } else if i == 0 {
+                            // Ghislane
+                            self.add(if self.at(2) == 'I' { "J" } else { "K" }, "K");
+                        }
+                        // otherwise silent, as in "Hugh" or "Wright"
+                        step = 2;
+                    } else if next == 'N' {
+                        self.add("N", "KN");
+                        step = 2;
+                    } else if matches!(next, 'I' | 'E' | 'Y') {
+                        // George, Gerhard
+                        self.add("J", "K");
+                        step = 2;
+                    } else {
+                        self.add("K", "K");
+                        if next == 'G' {
+                            step = 2;
+                        }
+                    }
+                }
+                'H' => {
+                    // only pronounced at the start or between vowels
+                    if (i == 0 || is_vowel(self.at(i - 1))) && is_vowel(next) {
+                        self.add("H", "H");
+                        step = 2;
+                    }
+                }
+                'J' => {
+                    // Jose is pronounced with an H in Spanish
+                    self.add("J", if i == 0 { "H" } else { "J" });
+                    if next == 'J' {
+                        step = 2;
+                    }
+                }
+                'P' => {
+                    if next == 'H' {
+                        self.add("F", "F");
+                        step = 2;
+                    } else {
+                        self.add("P", "P");
+                        if matches!(next, 'P' | 'B') {
+                            step = 2;
+                        }
+                    }
+                }
+                'Q' => {
+                    self.add("K", "K");
+                    if next == 'Q' {
+                        step = 2;
+                    }
+                }
+                'S' => {
+                    if i == 0 && self.starts_with_at(i, "SCH") {
+                        // Schmidt, Schneider
+                        if is_vowel(self.at(i + 3)) {
+                            self.add("SK", "X");
+                        } else {
+                            self.add("X", "S");
+                        }
+                        step = 3;
+                    } else if self.starts_with_at(i, "SCH") {
+                        self.add("SK", "SK");
+                        step = 3;
+                    } else if next == 'H' {
+                        self.add("X", "X");
+                        step = 2;
+                    } else if self.starts_with_at(i, "SIO") || self.starts_with_at(i, "SIA") {
+                        self.add("S", "X");
+                        step = 3;
+                    } else if i == 0 && matches!(next, 'M' | 'N' | 'L' | 'W') {
+                        // Smith may be the anglicized Schmidt
+                        self.add("S", "X");
+                    } else if next == 'C' && matches!(self.at(i + 2), 'I' | 'E' | 'Y') {
+                        self.add("S", "S");
+                        step = 3;
+                    } else if next == 'C' {
+                        self.add("SK", "SK");
+                        step = 2;
+                    } else {
+                        self.add("S", "S");
+                        if matches!(next, 'S' | 'Z') {
+                            step = 2;
+                        }
+                    }
+                }
+                'T' => {
+                    if ["TIO", "TIA", "TCH"]
+                        .iter()
+                        .any(|p| self.starts_with_at(i, p))
+                    {
+                        self.add("X", "X");
+                        step = 3;
+                    } else if next == 'H' {
+                        self.add("0", "T");
+                        step = 2;
+                    } else {
+                        self.add("T", "T");
+                        if matches!(next, 'T' | 'D') {
+                            step = 2;
+                        }
+                    }
+                }
+                'V' => {
+                    self.add("F", "F");
+                    if next == 'V' {
+                        step = 2;
+                    }
+                }
+                'W' => {
+                    // only pronounced at the start, as in "Walter"
+                    if i == 0 && is_vowel(next) {
+                        self.add("A", "F");
+                    }
+                }
+                'X' => {
+                    self.add("KS", "KS");
+                    if matches!(next, 'C' | 'X') {
+                        step = 2;
+                    }
+                }
+                'Z' => {
+                    self.add("S", "TS");
+                    if next == 'Z' {
+                        step = 2;
+                    }
+                }
+                _ => {
+                    // F, K, L, M, N and R are pronounced as written
+                    self.add(&c.to_string(), &c.to_string());
+                    if next == c {
+                        step = 2;
+                    }
+                }
+            }
+            i += step;
+        }
+        self.primary.truncate(MAX_CODE_LEN);
+        self.alternate.truncate(MAX_CODE_LEN);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sounds_like(a: &str, b: &str) -> bool {
+        encode(a).sounds_like(&encode(b))
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!("SM0", encode("Smith").primary);
+        assert_eq!("XMT", encode("Smith").alternate);
+        assert_eq!("XMT", encode("Schmidt").primary);
+        assert_eq!("KRST", encode("Christopher").primary);
+        assert_eq!("FLPS", encode("Phillips").primary);
+    }
+
+    #[test]
+    fn similar_names() {
+        assert!(sounds_like("Smith", "Smyth"));
+        assert!(sounds_like("Smith", "Schmidt"));
+        assert!(sounds_like("Catherine", "Kathryn"));
+        assert!(sounds_like("Stephen", "Steven"));
+        assert!(sounds_like("Jose", "José"));
+        assert!(!sounds_like("Smith", "Jones"));
+    }
+
+    #[test]
+    fn index_is_maintained() {
+        let mut e = Entry {
+            id: 1,
+            first_name: "John".to_string(),
+            last_name: "Smith".to_string(),
+            ..Default::default()
+        };
+        let mut index = PhoneticIndex::build(&[e.clone()]);
+        assert!(index.matches(1, &encode_words("Jon Smyth")));
+        e.last_name = "Brown".to_string();
+        index.insert(&e);
+        assert!(!index.matches(1, &encode_words("Smyth")));
+        assert!(index.matches(1, &encode_words("Braun")));
+        index.remove(1);
+        assert!(!index.matches(1, &encode_words("Braun")));
+    }
+}
@@ -0,0 +1,520 @@
+use crate::csv::{self, CsvImport};
+use crate::entry::Entry;
+use crate::import::RowError;
+use crate::query::Field;
+
+/// CSV layout of another address book.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Profile {
+    /// Google Contacts, both the current layout (`First Name`, `Phone 1 -
+    /// Value`) and the older one (`Given Name`, `Phone 1 - Type`).
+    Google,
+    /// Outlook, with its `Mobile Phone`, `Home Street`, ... columns.
+    Outlook,
+}
+
+// separates several values in one Google Contacts cell
+const GOOGLE_SEPARATOR: &str = " ::: ";
+
+// phone columns of Outlook, most useful first
+const OUTLOOK_PHONES: [&str; 11] = [
+    "Mobile Phone",
+    "Primary Phone",
+    "Business Phone",
+    "Home Phone",
+    "Business Phone 2",
+    "Home Phone 2",
+    "Other Phone",
+    "Company Main Phone",
+    "Car Phone",
+    "Assistant's Phone",
+    "Callback",
+];
+
+const OUTLOOK_EMAILS: [&str; 3] = ["E-mail Address", "E-mail 2 Address", "E-mail 3 Address"];
+
+// address groups of Outlook, most useful first
+const OUTLOOK_ADDRESSES: [&str; 3] = ["Home", "Business", "Other"];
+
+const ADDRESS_PARTS: [&str; 5] = ["Street", "City", "State", "Postal Code", "Country/Region"];
+
+/// Values of one record by header name.
+struct Row<'a> {
+    header: &'a [String],
+    fields: &'a [String],
+    /// Columns read so far, to report the others as ignored.
+    used: Vec<bool>,
+}
+
+impl Row<'_> {
+    /// Returns the trimmed value of a column, empty if there is none.
+    fn get(&mut self, name: &str) -> String {
+        match self
+            .header
+            .iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name))
+        {
+            Some(i) => {
+                self.used[i] = true;
+                self.fields[i].trim().to_string()
+            }
+            None => "".to_string(),
+        }
+    }
+
+    /// Returns the first non-empty value of the columns.
+    fn first(&mut self, names: &[&str]) -> String {
+        let mut result = "".to_string();
+        for name in names.iter() {
+            let value = self.get(name);
+            if result.is_empty() {
+                result = value;
+            }
+        }
+        result
+    }
+
+    /// Returns the values of numbered Google columns such as `Phone 1 -
+    /// Value`, `Phone 2 - Value`, ... split at the value separator.
+    fn numbered(&mut self, group: &str, part: &str) -> Vec<String> {
+        let mut values: Vec<String> = Vec::new();
+        for n in 1.. {
+            let name = format!("{} {} - {}", group, n, part);
+            if !self
+                .header
+                .iter()
+                .any(|h| h.trim().eq_ignore_ascii_case(&name))
+            {
+                break;
+            }
+            let value = self.get(&name);
+            // labels such as "Mobile" or "Work" have no field of their own
+            self.get(format!("{} {} - Label", group, n).as_str());
+            self.get(format!("{} {} - Type", group, n).as_str());
+            values.extend(
+                value
+                    .split(GOOGLE_SEPARATOR)
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| !v.is_empty()),
+            );
+        }
+        values
+    }
+}
+
+/// Joins the non-empty parts of an address.
+fn join_address(parts: Vec<String>) -> String {
+    let parts: Vec<String> = parts.into_iter().filter(|p| !p.is_empty()).collect();
+    parts.join(", ").replace('\n', ", ")
+}
+
+/// Keeps further values of a field as a custom field, separated by commas,
+/// like the abook and mutt importers do.
+fn keep_others(e: &mut Entry, name: &str, values: Vec<String>) {
+    if !values.is_empty() {
+        e.custom.insert(name.to_string(), values.join(", "));
+    }
+}
+
+/// Returns the further values kept in a custom field by `keep_others`.
+fn others(e: &Entry, name: &str) -> Vec<String> {
+    match e.custom.get(name) {
+        Some(v) => v
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Converts an Outlook birthday (`M/D/YYYY`, `0/0/00` if not set) to
+/// `YYYY-MM-DD`, keeping other values as they are.
+fn outlook_birthday(s: &str) -> String {
+    let parts: Vec<&str> = s.split('/').collect();
+    if parts.len() != 3 {
+        return s.to_string();
+    }
+    match (
+        parts[0].parse::<u32>(),
+        parts[1].parse::<u32>(),
+        parts[2].parse::<u32>(),
+    ) {
+        (Ok(0), _, _) | (_, Ok(0), _) => "".to_string(),
+        (Ok(month), Ok(day), Ok(year)) if parts[2].len() == 4 => {
+            format!("{:04}-{:02}-{:02}", year, month, day)
+        }
+        _ => s.to_string(),
+    }
+}
+
+impl Profile {
+    pub fn from(s: &str) -> Result<Profile, String> {
+        match s {
+            "google" => Ok(Profile::Google),
+            "outlook" => Ok(Profile::Outlook),
+            _ => Err(format!("unknown profile {}", s)),
+        }
+    }
+
+    fn read_google(row: &mut Row) -> Entry {
+        let mut e = Entry {
+            first_name: row.first(&["First Name", "Given Name"]),
+            last_name: row.first(&["Last Name", "Family Name"]),
+            organization: row.first(&["Organization Name", "Organization 1 - Name"]),
+            title: row.first(&["Organization Title", "Organization 1 - Title"]),
+            birthday: row.get("Birthday"),
+            note: row.get("Notes"),
+            ..Default::default()
+        };
+        let name = row.first(&["Name", "File As"]);
+        if e.first_name.is_empty() && e.last_name.is_empty() {
+            e.set(Field::Name, &name);
+        }
+        // further phone numbers and e-mails are kept as custom fields
+        let mut phones = row.numbered("Phone", "Value");
+        if !phones.is_empty() {
+            e.phone = phones.remove(0);
+        }
+        keep_others(&mut e, "phone", phones);
+        let mut emails = row.numbered("E-mail", "Value");
+        if !emails.is_empty() {
+            e.e_mail = emails.remove(0);
+        }
+        keep_others(&mut e, "email", emails);
+        e.address = row
+            .numbered("Address", "Formatted")
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        let street = row.numbered("Address", "Street");
+        if e.address.is_empty() && !street.is_empty() {
+            let mut parts = vec![street[0].clone()];
+            for part in ["City", "Region", "Postal Code", "Country"] {
+                parts.push(
+                    row.numbered("Address", part)
+                        .into_iter()
+                        .next()
+                        .unwrap_or_default(),
+                );
+            }
+            e.address = join_address(parts);
+        }
+        e.address = e.address.replace('\n', ", ");
+        // labels such as "* myContacts" and "* starred" are set by Google
+        let labels = row.first(&["Labels", "Group Membership"]);
+        e.tags = labels
+            .split(GOOGLE_SEPARATOR)
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with("* "))
+            .map(|l| l.to_string())
+            .collect();
+        e
+    }
+
+    fn read_outlook(row: &mut Row) -> Entry {
+        let mut e = Entry {
+            first_name: row.get("First Name"),
+            last_name: row.get("Last Name"),
+            organization: row.get("Company"),
+            title: row.get("Job Title"),
+            note: row.get("Notes"),
+            ..Default::default()
+        };
+        e.birthday = outlook_birthday(&row.get("Birthday"));
+        // further phone numbers are kept by column, further e-mails together
+        let phones: Vec<(&str, String)> = OUTLOOK_PHONES
+            .iter()
+            .map(|c| (*c, row.get(c)))
+            .filter(|(_, v)| !v.is_empty())
+            .collect();
+        if let Some(((_, first), rest)) = phones.split_first() {
+            e.phone = first.clone();
+            for (column, value) in rest.iter() {
+                e.custom.insert(column.to_string(), value.clone());
+            }
+        }
+        let mut emails: Vec<String> = OUTLOOK_EMAILS
+            .iter()
+            .map(|c| row.get(c))
+            .filter(|v| !v.is_empty())
+            .collect();
+        if !emails.is_empty() {
+            e.e_mail = emails.remove(0);
+        }
+        keep_others(&mut e, "email", emails);
+        for group in OUTLOOK_ADDRESSES.iter() {
+            let parts: Vec<String> = ADDRESS_PARTS
+                .iter()
+                .map(|p| row.get(format!("{} {}", group, p).as_str()))
+                .collect();
+            if e.address.is_empty() {
+                e.address = join_address(parts);
+            }
+        }
+        e.tags = Entry::parse_tags(row.get("Categories").replace(';', ",").as_str());
+        e
+    }
+
+    /// Reads entries from a CSV file in the layout of the profile. Records
+    /// that can not be read are reported without stopping the import.
+    pub fn read_entries(&self, text: &str, delimiter: Option<char>) -> Result<CsvImport, String> {
+        let delimiter = delimiter.unwrap_or_else(|| csv::detect_delimiter(text));
+        let (records, mut errors) = csv::parse(text, delimiter);
+        let mut records = records.into_iter();
+        let header = match records.next() {
+            Some(x) => x.fields,
+            None => return Err("file has no header".to_string()),
+        };
+        let mut used = vec![false; header.len()];
+        let mut entries: Vec<(usize, Entry)> = Vec::new();
+        for record in records {
+            if record.fields.len() != header.len() {
+                errors.push(RowError {
+                    line: record.line,
+                    message: format!(
+                        "expected {} fields, found {}",
+                        header.len(),
+                        record.fields.len()
+                    ),
+                });
+                continue;
+            }
+            let mut row = Row {
+                header: &header,
+                fields: &record.fields,
+                used: vec![false; header.len()],
+            };
+            let e = match self {
+                Profile::Google => Profile::read_google(&mut row),
+                Profile::Outlook => Profile::read_outlook(&mut row),
+            };
+            for (u, r) in used.iter_mut().zip(row.used.iter()) {
+                *u = *u || *r;
+            }
+            entries.push((record.line, e));
+        }
+        errors.sort_by_key(|e| e.line);
+        let ignored = match entries.is_empty() {
+            true => Vec::new(),
+            false => header
+                .iter()
+                .zip(used.iter())
+                .filter(|(_, u)| !**u)
+                .map(|(h, _)| h.clone())
+                .collect(),
+        };
+        Ok(CsvImport {
+            entries,
+            errors,
+            ignored,
+        })
+    }
+
+    /// Writes entries as CSV in the layout of the profile.
+    pub fn write_entries(&self, entries: &[Entry]) -> String {
+        let (header, rows): (Vec<&str>, Vec<Vec<String>>) = match self {
+            Profile::Google => (
+                vec![
+                    "First Name",
+                    "Last Name",
+                    "Birthday",
+                    "Notes",
+                    "Labels",
+                    "E-mail 1 - Label",
+                    "E-mail 1 - Value",
+                    "Phone 1 - Label",
+                    "Phone 1 - Value",
+                    "Address 1 - Label",
+                    "Address 1 - Formatted",
+                    "Organization Name",
+                    "Organization Title",
+                ],
+                entries
+                    .iter()
+                    .map(|e| {
+                        let mut labels = vec!["* myContacts".to_string()];
+                        labels.extend(e.tags.iter().cloned());
+                        let label = |value: &str, label: &str| match value.is_empty() {
+                            true => "".to_string(),
+                            false => label.to_string(),
+                        };
+                        // further values share the cell of the first one
+                        let with_others = |value: &str, name: &str| {
+                            let mut values = vec![value.to_string()];
+                            values.extend(others(e, name));
+                            values.retain(|v| !v.is_empty());
+                            values.join(GOOGLE_SEPARATOR)
+                        };
+                        vec![
+                            e.first_name.clone(),
+                            e.last_name.clone(),
+                            e.birthday.clone(),
+                            e.note.clone(),
+                            labels.join(GOOGLE_SEPARATOR),
+                            label(&e.e_mail, "* Other"),
+                            with_others(&e.e_mail, "email"),
+                            label(&e.phone, "Mobile"),
+                            with_others(&e.phone, "phone"),
+                            label(&e.address, "Home"),
+                            e.address.clone(),
+                            e.organization.clone(),
+                            e.title.clone(),
+                        ]
+                    })
+                    .collect(),
+            ),
+            Profile::Outlook => (
+                [
+                    "First Name",
+                    "Last Name",
+                    "Company",
+                    "Job Title",
+                    "Home Street",
+                    "Birthday",
+                    "Notes",
+                    "Categories",
+                ]
+                .into_iter()
+                .chain(OUTLOOK_EMAILS)
+                .chain(OUTLOOK_PHONES)
+                .collect(),
+                entries
+                    .iter()
+                    .map(|e| {
+                        // Outlook writes dates as M/D/YYYY
+                        let birthday = match e.birthday.split('-').collect::<Vec<&str>>()[..] {
+                            [y, m, d] if y.len() == 4 => format!(
+                                "{}/{}/{}",
+                                m.trim_start_matches('0'),
+                                d.trim_start_matches('0'),
+                                y
+                            ),
+                            _ => "".to_string(),
+                        };
+                        let mut emails = others(e, "email");
+                        let rest = emails.split_off(emails.len().min(1));
+                        emails.insert(0, e.e_mail.clone());
+                        emails.push(rest.join(", "));
+                        emails.resize(OUTLOOK_EMAILS.len(), "".to_string());
+                        let phones = OUTLOOK_PHONES
+                            .iter()
+                            .skip(1)
+                            .map(|c| e.custom.get(*c).cloned().unwrap_or_default());
+                        let mut row = vec![
+                            e.first_name.clone(),
+                            e.last_name.clone(),
+                            e.organization.clone(),
+                            e.title.clone(),
+                            e.address.clone(),
+                            birthday,
+                            e.note.clone(),
+                            e.tags.join(";"),
+                        ];
+                        row.extend(emails);
+                        row.push(e.phone.clone());
+                        row.extend(phones);
+                        row
+                    })
+                    .collect(),
+            ),
+        };
+        let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+        let mut result = csv::write_record(&header, ',');
+        for row in rows.iter() {
+            result.push_str(csv::write_record(row, ',').as_str());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn read_google() {
+        let text = "First Name,Middle Name,Last Name,Birthday,Labels,E-mail 1 - Label,E-mail 1 - Value,\
+            Phone 1 - Label,Phone 1 - Value,Phone 2 - Label,Phone 2 - Value,Address 1 - Label,\
+            Address 1 - Formatted,Address 1 - Street,Organization Name,Nickname\n\
+            John,Q,Smith,1985-04-12,* myContacts ::: Work ::: Friends,* Work,john@example.com ::: js@example.com,\
+            Work,,Mobile,+1 555 123 4567,Home,\"Main street 1\nSpringfield\",Main street 1,ACME,Johnny\n";
+        let import = Profile::Google.read_entries(text, None).unwrap();
+        assert!(import.errors.is_empty());
+        let e = &import.entries[0].1;
+        assert_eq!("John", e.first_name);
+        assert_eq!("Smith", e.last_name);
+        assert_eq!("+1 555 123 4567", e.phone);
+        assert_eq!("john@example.com", e.e_mail);
+        assert_eq!(Some(&"js@example.com".to_string()), e.custom.get("email"));
+        assert_eq!("Main street 1, Springfield", e.address);
+        assert_eq!("ACME", e.organization);
+        assert_eq!("1985-04-12", e.birthday);
+        assert_eq!(vec!["Work", "Friends"], e.tags);
+        assert_eq!(vec!["Middle Name", "Nickname"], import.ignored);
+    }
+
+    #[test]
+    fn read_outlook() {
+        let text = "First Name,Last Name,E-mail Address,Home Phone,Mobile Phone,Company,Job Title,\
+            Business Street,Business City,Business Postal Code,Birthday,Categories\n\
+            John,Smith,john@example.com,+1 555 000 1111,+1 555 123 4567,ACME,Engineer,\
+            Main street 1,Springfield,12345,4/12/1985,Work;Friends\n\
+            Mary,Jones,,,,,,,,,0/0/00,\n";
+        let import = Profile::Outlook.read_entries(text, None).unwrap();
+        let e = &import.entries[0].1;
+        assert_eq!("+1 555 123 4567", e.phone);
+        assert_eq!(
+            Some(&"+1 555 000 1111".to_string()),
+            e.custom.get("Home Phone")
+        );
+        assert_eq!("Main street 1, Springfield, 12345", e.address);
+        assert_eq!("Engineer", e.title);
+        assert_eq!("1985-04-12", e.birthday);
+        assert_eq!(vec!["Work", "Friends"], e.tags);
+        assert_eq!("", import.entries[1].1.birthday);
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let e = Entry {
+            first_name: "John".to_string(),
+            last_name: "Smith".to_string(),
+            phone: "+1 555 123 4567".to_string(),
+            e_mail: "john@example.com".to_string(),
+            address: "Main street 1, Springfield".to_string(),
+            birthday: "1985-04-02".to_string(),
+            tags: vec!["work".to_string()],
+            custom: BTreeMap::from([(
+                "email".to_string(),
+                "js@example.com, j@example.org".to_string(),
+            )]),
+            ..Default::default()
+        };
+        let mut google = e.clone();
+        google
+            .custom
+            .insert("phone".to_string(), "+1 555 000 1111".to_string());
+        let text = Profile::Google.write_entries(std::slice::from_ref(&google));
+        let import = Profile::Google.read_entries(&text, None).unwrap();
+        assert_eq!(google.custom, import.entries[0].1.custom);
+        let mut outlook = e.clone();
+        outlook
+            .custom
+            .insert("Home Phone".to_string(), "+1 555 000 1111".to_string());
+        let text = Profile::Outlook.write_entries(std::slice::from_ref(&outlook));
+        let import = Profile::Outlook.read_entries(&text, None).unwrap();
+        assert_eq!(outlook.custom, import.entries[0].1.custom);
+        for profile in [Profile::Google, Profile::Outlook] {
+            let text = profile.write_entries(std::slice::from_ref(&e));
+            let import = profile.read_entries(&text, None).unwrap();
+            let read = &import.entries[0].1;
+            assert_eq!(e.phone, read.phone);
+            assert_eq!(e.e_mail, read.e_mail);
+            assert_eq!(e.address, read.address);
+            assert_eq!(e.birthday, read.birthday);
+            assert_eq!(e.tags, read.tags);
+        }
+    }
+}
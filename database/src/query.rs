@@ -0,0 +1,441 @@
+use crate::entry::Entry;
+use crate::normalize::{self, MatchOptions};
+
+/// Entry field a search term is restricted to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Any,
+    Id,
+    Name,
+    FirstName,
+    LastName,
+    Phone,
+    Address,
+    Email,
+    Tag,
+    Organization,
+    Note,
+    Birthday,
+    Title,
+}
+
+impl Field {
+    pub fn from(s: &str) -> Result<Field, String> {
+        match s {
+            "id" => Ok(Field::Id),
+            "name" => Ok(Field::Name),
+            "first" | "first-name" => Ok(Field::FirstName),
+            "last" | "last-name" => Ok(Field::LastName),
+            "phone" => Ok(Field::Phone),
+            "address" => Ok(Field::Address),
+            "email" | "e-mail" | "mail" => Ok(Field::Email),
+            "tag" => Ok(Field::Tag),
+            "org" | "organization" => Ok(Field::Organization),
+            "note" => Ok(Field::Note),
+            "birthday" | "bday" => Ok(Field::Birthday),
+            "title" => Ok(Field::Title),
+            _ => Err(format!("unknown field {}", s)),
+        }
+    }
+
+    /// Name of the field as used in queries and settings.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Field::Any => "any",
+            Field::Id => "id",
+            Field::Name => "name",
+            Field::FirstName => "first",
+            Field::LastName => "last",
+            Field::Phone => "phone",
+            Field::Address => "address",
+            Field::Email => "email",
+            Field::Tag => "tag",
+            Field::Organization => "org",
+            Field::Note => "note",
+            Field::Birthday => "birthday",
+            Field::Title => "title",
+        }
+    }
+
+    /// Name of the field as shown in messages.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Field::Any => "any field",
+            Field::Id => "id",
+            Field::Name => "name",
+            Field::FirstName => "first name",
+            Field::LastName => "last name",
+            Field::Phone => "phone number",
+            Field::Address => "address",
+            Field::Email => "e-mail",
+            Field::Tag => "tags",
+            Field::Organization => "organization",
+            Field::Note => "note",
+            Field::Birthday => "birthday",
+            Field::Title => "title",
+        }
+    }
+}
+
+/// Syntax tree of a search query.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Term(Field, String),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    pub fn matches(&self, e: &Entry, options: MatchOptions) -> bool {
+        match self {
+            Expr::Term(field, value) => term_matches(*field, value, e, options),
+            Expr::Not(x) => !x.matches(e, options),
+            Expr::And(xs) => xs.iter().all(|x| x.matches(e, options)),
+            Expr::Or(xs) => xs.iter().any(|x| x.matches(e, options)),
+        }
+    }
+}
+
+/// Parsed search query.
+///
+/// Syntax: terms are separated by whitespace and implicitly joined with AND.
+/// A term may be scoped to a field (`last:smith`), quoted to search for a
+/// phrase containing spaces (`"my street"`), negated with a leading `-` or
+/// `NOT`, and combined with `AND`, `OR` and parentheses.
+///
+/// Text is compared according to `options`, which by default ignore case and
+/// diacritics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    pub expr: Expr,
+    pub options: MatchOptions,
+}
+
+impl Query {
+    pub fn parse(s: &str) -> Result<Query, String> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Err("query is empty".to_string());
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+        match parser.tokens.get(parser.pos) {
+            Some(Token::Close) => Err("unmatched )".to_string()),
+            Some(_) => Err("unexpected input at end of query".to_string()),
+            None => Ok(Query {
+                expr,
+                options: MatchOptions::default(),
+            }),
+        }
+    }
+
+    pub fn with_options(mut self, options: MatchOptions) -> Query {
+        self.options = options;
+        self
+    }
+
+    pub fn matches(&self, e: &Entry) -> bool {
+        self.expr.matches(e, self.options)
+    }
+}
+
+fn term_matches(field: Field, value: &str, e: &Entry, options: MatchOptions) -> bool {
+    let contains = |haystack: &str| normalize::contains(haystack, value, options);
+    match field {
+        Field::Any => {
+            term_matches(Field::Name, value, e, options)
+                || contains(&e.phone)
+                || contains(&e.address)
+                || contains(&e.e_mail)
+                || contains(&e.organization)
+                || contains(&e.note)
+                || contains(&e.title)
+                || term_matches(Field::Tag, value, e, options)
+        }
+        Field::Id => e.id.to_string() == value,
+        Field::Name => contains(&format!("{} {}", e.first_name, e.last_name)),
+        Field::FirstName => contains(&e.first_name),
+        Field::LastName => contains(&e.last_name),
+        Field::Phone => contains(&e.phone),
+        Field::Address => contains(&e.address),
+        Field::Email => contains(&e.e_mail),
+        Field::Tag => e.tags.iter().any(|t| normalize::equals(t, value, options)),
+        Field::Organization => contains(&e.organization),
+        Field::Note => contains(&e.note),
+        Field::Birthday => contains(&e.birthday),
+        Field::Title => contains(&e.title),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Term(Field, String),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '-' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            _ => {
+                let mut word = String::new();
+                let mut quoted = false;
+                let mut field = Field::Any;
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    match c {
+                        '"' => {
+                            word.push_str(read_phrase(&mut chars)?.as_str());
+                            quoted = true;
+                        }
+                        // a word before a colon that is no field, e.g. 12:30, is searched as it is
+                        ':' if field == Field::Any && !quoted && !word.is_empty() => {
+                            match Field::from(word.as_str()) {
+                                Ok(f) => {
+                                    field = f;
+                                    word.clear();
+                                }
+                                Err(_) => word.push(c),
+                            }
+                        }
+                        _ => word.push(c),
+                    }
+                }
+                if word.is_empty() {
+                    return Err(match field {
+                        Field::Any => "empty search term".to_string(),
+                        _ => "missing value after field name".to_string(),
+                    });
+                }
+                let token = match word.as_str() {
+                    "AND" if !quoted && field == Field::Any => Token::And,
+                    "OR" if !quoted && field == Field::Any => Token::Or,
+                    "NOT" if !quoted && field == Field::Any => Token::Not,
+                    _ => Token::Term(field, word),
+                };
+                tokens.push(token);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn read_phrase(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    let mut phrase = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            return Ok(phrase);
+        }
+        phrase.push(c);
+    }
+    Err("unterminated quote".to_string())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut parts = vec![self.parse_and()?];
+        while let Some(Token::Or) = self.peek() {
+            self.pos += 1;
+            parts.push(self.parse_and()?);
+        }
+        Ok(match parts.len() {
+            1 => parts.remove(0),
+            _ => Expr::Or(parts),
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut parts = vec![self.parse_unary()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.pos += 1;
+                    parts.push(self.parse_unary()?);
+                }
+                Some(Token::Or) | Some(Token::Close) | None => break,
+                Some(_) => parts.push(self.parse_unary()?),
+            }
+        }
+        Ok(match parts.len() {
+            1 => parts.remove(0),
+            _ => Expr::And(parts),
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let q = self.parse_or()?;
+                match self.peek() {
+                    Some(Token::Close) => {
+                        self.pos += 1;
+                        Ok(q)
+                    }
+                    _ => Err("missing )".to_string()),
+                }
+            }
+            Some(Token::Term(field, value)) => {
+                let q = Expr::Term(*field, value.clone());
+                self.pos += 1;
+                Ok(q)
+            }
+            Some(Token::Close) => Err("unexpected )".to_string()),
+            Some(Token::And) | Some(Token::Or) => Err("operator without operand".to_string()),
+            None => Err("unexpected end of query".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(field: Field, value: &str) -> Expr {
+        Expr::Term(field, value.to_string())
+    }
+
+    fn entry() -> Entry {
+        Entry {
+            id: 3,
+            first_name: "John".to_string(),
+            last_name: "Smith".to_string(),
+            phone: "555 987".to_string(),
+            address: "Main street 12".to_string(),
+            e_mail: "js@gmail.com".to_string(),
+            tags: vec!["work".to_string(), "old".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_scoped_terms_and_phrase() {
+        let q = Query::parse("last:smith phone:555 -tag:old \"exact phrase\"")
+            .unwrap()
+            .expr;
+        assert_eq!(
+            Expr::And(vec![
+                term(Field::LastName, "smith"),
+                term(Field::Phone, "555"),
+                Expr::Not(Box::new(term(Field::Tag, "old"))),
+                term(Field::Any, "exact phrase"),
+            ]),
+            q
+        );
+    }
+
+    #[test]
+    fn parse_operator_precedence() {
+        let q = Query::parse("a OR b c AND NOT (d OR e)").unwrap().expr;
+        assert_eq!(
+            Expr::Or(vec![
+                term(Field::Any, "a"),
+                Expr::And(vec![
+                    term(Field::Any, "b"),
+                    term(Field::Any, "c"),
+                    Expr::Not(Box::new(Expr::Or(vec![
+                        term(Field::Any, "d"),
+                        term(Field::Any, "e")
+                    ]))),
+                ]),
+            ]),
+            q
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Err("missing )".to_string()), Query::parse("(a OR b"));
+        assert_eq!(Err("unmatched )".to_string()), Query::parse("a)"));
+        assert_eq!(Err("unterminated quote".to_string()), Query::parse("\"abc"));
+        assert_eq!(
+            Err("operator without operand".to_string()),
+            Query::parse("OR a")
+        );
+    }
+
+    #[test]
+    fn parse_unknown_prefix_as_term() {
+        assert_eq!(
+            term(Field::Any, "12:30"),
+            Query::parse("12:30").unwrap().expr
+        );
+        assert_eq!(
+            term(Field::Note, "at 12:30"),
+            Query::parse("note:\"at 12:30\"").unwrap().expr
+        );
+        assert_eq!(
+            term(Field::Any, "foo:name:x"),
+            Query::parse("foo:name:x").unwrap().expr
+        );
+    }
+
+    #[test]
+    fn scoped_term_does_not_match_other_fields() {
+        let e = entry();
+        assert!(Query::parse("12").unwrap().matches(&e));
+        assert!(!Query::parse("phone:12").unwrap().matches(&e));
+        assert!(Query::parse("address:12").unwrap().matches(&e));
+    }
+
+    #[test]
+    fn match_phrase_tags_and_negation() {
+        let e = entry();
+        assert!(Query::parse("\"John Smith\" tag:work").unwrap().matches(&e));
+        assert!(!Query::parse("last:Smith -tag:old").unwrap().matches(&e));
+        assert!(Query::parse("first:Jane OR id:3").unwrap().matches(&e));
+    }
+
+    #[test]
+    fn match_options_apply_to_every_field() {
+        let mut e = entry();
+        e.first_name = "José".to_string();
+        e.tags = vec!["Παλιό".to_string()];
+        assert!(Query::parse("first:jose tag:παλιο").unwrap().matches(&e));
+        let exact = Query::parse("first:jose")
+            .unwrap()
+            .with_options(MatchOptions::exact());
+        assert!(!exact.matches(&e));
+        let exact = Query::parse("first:José")
+            .unwrap()
+            .with_options(MatchOptions::exact());
+        assert!(exact.matches(&e));
+    }
+}
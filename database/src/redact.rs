@@ -0,0 +1,216 @@
+use crate::entry::Entry;
+use crate::meta::Metadata;
+use crate::query::Field;
+
+/// Fields masked when redaction is asked for but none are configured.
+const DEFAULT_FIELDS: [Field; 3] = [Field::Phone, Field::Address, Field::Email];
+
+/// Which fields are masked when entries are shared. Stored in metadata as
+/// `redact=<fields>` for all entries and `redact.<tag>=<fields>` for entries
+/// with a tag; `redact_by_default=true` masks them unless asked not to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Redaction {
+    pub fields: Vec<Field>,
+    pub tags: Vec<(String, Vec<Field>)>,
+    pub by_default: bool,
+}
+
+fn parse_fields(s: &str) -> Result<Vec<Field>, String> {
+    let mut fields: Vec<Field> = Vec::new();
+    for name in s.split(',').map(|f| f.trim()).filter(|f| !f.is_empty()) {
+        match Field::from(name)? {
+            Field::Id | Field::Any => return Err(format!("{} can not be redacted", name)),
+            f => fields.push(f),
+        }
+    }
+    Ok(fields)
+}
+
+impl Redaction {
+    pub fn from_metadata(meta: &Metadata) -> Result<Redaction, String> {
+        let mut redaction = Redaction::default();
+        for (key, value) in meta.iter() {
+            if key == "redact" {
+                redaction.fields = parse_fields(value)?;
+            } else if let Some(tag) = key.strip_prefix("redact.") {
+                redaction.tags.push((tag.to_string(), parse_fields(value)?));
+            } else if key == "redact_by_default" {
+                redaction.by_default = match value {
+                    "true" | "yes" => true,
+                    "false" | "no" => false,
+                    _ => {
+                        return Err(format!(
+                            "redact_by_default must be true or false, not {}",
+                            value
+                        ))
+                    }
+                };
+            }
+        }
+        Ok(redaction)
+    }
+
+    /// Returns the fields to mask in an entry, phone, address and e-mail if
+    /// no fields are configured at all.
+    fn fields_of(&self, e: &Entry) -> Vec<Field> {
+        if self.fields.is_empty() && self.tags.is_empty() {
+            return DEFAULT_FIELDS.to_vec();
+        }
+        let mut fields = self.fields.clone();
+        for (_, tag_fields) in self.tags.iter().filter(|(t, _)| e.tags.contains(t)) {
+            fields.extend(tag_fields.iter().copied());
+        }
+        fields
+    }
+
+    /// Returns a copy of an entry with the fields of the policy masked.
+    /// Custom fields can hold anything, so they are always masked.
+    pub fn apply(&self, e: &Entry) -> Entry {
+        let mut result = e.clone();
+        for value in result.custom.values_mut() {
+            *value = mask_custom(value);
+        }
+        for field in self.fields_of(e) {
+            let masked = mask(field, e.get(field).as_str());
+            match field {
+                // the canonical form would reveal the number
+                Field::Phone => {
+                    result.phone = masked;
+                    result.phone_e164 = "".to_string();
+                }
+                Field::Name => {
+                    result.first_name = mask(Field::FirstName, &e.first_name);
+                    result.last_name = mask(Field::LastName, &e.last_name);
+                }
+                _ => result.set(field, masked.as_str()),
+            }
+        }
+        result
+    }
+}
+
+/// Masks a value, keeping its shape: phone numbers keep the last two digits,
+/// and the first four if at least half stay hidden (`+1 555 *** **34`),
+/// e-mail addresses the first letter and the domain (`j***@example.com`),
+/// other values the first letter of every word (`M*** s***** *`).
+pub fn mask(field: Field, value: &str) -> String {
+    match field {
+        Field::Phone => {
+            let digits = value.chars().filter(|c| c.is_ascii_digit()).count();
+            // the leading digits are only shown if at least half stay hidden
+            let keep_first = match digits > 6 && digits - 6 >= digits / 2 {
+                true => 4,
+                false => 0,
+            };
+            let mut i = 0;
+            value
+                .chars()
+                .map(|c| match c.is_ascii_digit() {
+                    true => {
+                        i += 1;
+                        match i <= keep_first || i + 2 > digits {
+                            true => c,
+                            false => '*',
+                        }
+                    }
+                    false => c,
+                })
+                .collect()
+        }
+        Field::Email => match value.split_once('@') {
+            Some((local, domain)) => {
+                let first: String = local.chars().take(1).collect();
+                format!("{}***@{}", first, domain)
+            }
+            None => mask(Field::Note, value),
+        },
+        _ => {
+            let mut in_word = false;
+            value
+                .chars()
+                .map(|c| {
+                    let keep = !c.is_alphanumeric() || !in_word;
+                    in_word = c.is_alphanumeric();
+                    match keep {
+                        true => c,
+                        false => '*',
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// Masks the value of a custom field like a phone number or e-mail address
+/// if it looks like one.
+fn mask_custom(value: &str) -> String {
+    let phone_like = value.chars().filter(|c| c.is_ascii_digit()).count() > 2
+        && value
+            .chars()
+            .all(|c| c.is_ascii_digit() || " +-()./".contains(c));
+    match (value.contains('@'), phone_like) {
+        (true, _) => mask(Field::Email, value),
+        (false, true) => mask(Field::Phone, value),
+        (false, false) => mask(Field::Note, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_values() {
+        assert_eq!("+1 555 *** **34", mask(Field::Phone, "+1 555 123 4534"));
+        assert_eq!("****34", mask(Field::Phone, "123434"));
+        assert_eq!("*** **34", mask(Field::Phone, "555 1234"));
+        assert_eq!("(***) ***-**67", mask(Field::Phone, "(555) 123-4567"));
+        assert_eq!(
+            "j***@example.com",
+            mask(Field::Email, "john.smith@example.com")
+        );
+        assert_eq!("M*** s*****, 1*", mask(Field::Address, "Main street, 12"));
+    }
+
+    #[test]
+    fn redact_custom_fields() {
+        let mut e = Entry::default();
+        e.custom
+            .insert("email".to_string(), "private@home.org".to_string());
+        e.custom
+            .insert("workphone".to_string(), "+1 555 000 1111".to_string());
+        e.custom
+            .insert("city".to_string(), "Springfield".to_string());
+        let redaction = Redaction::from_metadata(&Metadata::default()).unwrap();
+        let redacted = redaction.apply(&e);
+        assert_eq!("p***@home.org", redacted.custom["email"]);
+        assert_eq!("+1 555 *** **11", redacted.custom["workphone"]);
+        assert_eq!("S**********", redacted.custom["city"]);
+    }
+
+    #[test]
+    fn redact_by_tag() {
+        let mut meta = Metadata::default();
+        meta.set("redact", "address");
+        meta.set("redact.private", "email, phone");
+        let redaction = Redaction::from_metadata(&meta).unwrap();
+        let mut e = Entry {
+            phone: "+1 555 123 4534".to_string(),
+            phone_e164: "+15551234534".to_string(),
+            address: "Main street 1".to_string(),
+            e_mail: "john@example.com".to_string(),
+            ..Default::default()
+        };
+        let redacted = redaction.apply(&e);
+        assert_eq!("M*** s***** 1", redacted.address);
+        assert_eq!(e.e_mail, redacted.e_mail);
+        e.tags = vec!["private".to_string()];
+        let redacted = redaction.apply(&e);
+        assert_eq!("j***@example.com", redacted.e_mail);
+        assert_eq!("+1 555 *** **34", redacted.phone);
+        assert_eq!("", redacted.phone_e164);
+
+        meta.set("redact", "id");
+        assert!(Redaction::from_metadata(&meta).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::meta::Metadata;
+use crate::time::DateTime;
+use std::fs;
+use std::path::Path;
+
+/// Copy of a phonebook file taken before it was changed.
+pub struct Snapshot {
+    /// File name in the snapshot directory, the time it was taken followed by
+    /// the extension of the phonebook, e.g. `20261019T101500Z.txt`.
+    pub name: String,
+    pub created_at: u64,
+}
+
+/// How many snapshots are kept, read from the `snapshots` (number of
+/// snapshots) and `snapshot_days` (age in days) metadata keys. Snapshots are
+/// only taken if `snapshots` is set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Policy {
+    pub count: usize,
+    /// Snapshots older than this are removed, 0 keeps them regardless of age.
+    pub days: u64,
+}
+
+impl Policy {
+    pub fn from_metadata(meta: &Metadata) -> Result<Option<Policy>, String> {
+        let count = match meta.get("snapshots") {
+            None => return Ok(None),
+            Some(s) => match s.parse::<usize>() {
+                Ok(0) => return Ok(None),
+                Ok(x) => x,
+                Err(_) => return Err(format!("snapshots must be a number, not {}", s)),
+            },
+        };
+        let days = match meta.get("snapshot_days") {
+            None => 0,
+            Some(s) => match s.parse::<u64>() {
+                Ok(x) => x,
+                Err(_) => return Err(format!("snapshot_days must be a number, not {}", s)),
+            },
+        };
+        Ok(Some(Policy { count, days }))
+    }
+}
+
+/// Directory holding the snapshots of a phonebook, next to its file.
+pub fn dir(path: &str) -> String {
+    format!("{}.snapshots", path)
+}
+
+fn created_at(name: &str) -> Option<u64> {
+    DateTime::parse(name.get(..16)?)?.timestamp()
+}
+
+/// Lists the snapshots of a phonebook, oldest first.
+pub fn list(path: &str) -> Result<Vec<Snapshot>, String> {
+    let files = match fs::read_dir(dir(path)) {
+        Ok(x) => x,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("failed to read snapshots: {}", e)),
+    };
+    let mut snapshots: Vec<Snapshot> = files
+        .filter_map(|f| f.ok())
+        .filter_map(|f| f.file_name().into_string().ok())
+        .filter_map(|name| created_at(&name).map(|created_at| Snapshot { name, created_at }))
+        .collect();
+    // numbered snapshots of the same second are longer, -10 after -9
+    snapshots.sort_by(|a, b| {
+        (a.created_at, a.name.len(), &a.name).cmp(&(b.created_at, b.name.len(), &b.name))
+    });
+    Ok(snapshots)
+}
+
+/// Copies the phonebook file into its snapshot directory. Returns the name of
+/// the snapshot, or None if there was no file or it was empty.
+pub fn take(path: &str, now: u64) -> Result<Option<String>, String> {
+    let content = match fs::read(path) {
+        Ok(x) if !x.is_empty() => x,
+        _ => return Ok(None),
+    };
+    let dir = dir(path);
+    if let Err(e) = fs::create_dir_all(dir.as_str()) {
+        return Err(format!("failed to create {}: {}", dir, e));
+    }
+    let extension = match Path::new(path).extension().and_then(|x| x.to_str()) {
+        Some(x) => format!(".{}", x),
+        None => "".to_string(),
+    };
+    // snapshots taken within the same second are numbered
+    let time = DateTime::from_timestamp(now).to_basic();
+    let mut name = format!("{}{}", time, extension);
+    let mut n = 1;
+    while Path::new(dir.as_str()).join(name.as_str()).exists() {
+        n += 1;
+        name = format!("{}-{}{}", time, n, extension);
+    }
+    match fs::write(Path::new(dir.as_str()).join(name.as_str()), content) {
+        Ok(()) => Ok(Some(name)),
+        Err(e) => Err(format!("failed to write snapshot: {}", e)),
+    }
+}
+
+/// Removes the snapshots beyond the number to keep, oldest first, and those
+/// older than the policy allows.
+pub fn prune(path: &str, policy: Policy, now: u64) -> Result<(), String> {
+    let snapshots = list(path)?;
+    let excess = snapshots.len().saturating_sub(policy.count);
+    let oldest = now.saturating_sub(policy.days * 86400);
+    for (i, s) in snapshots.iter().enumerate() {
+        if i < excess || (policy.days > 0 && s.created_at < oldest) {
+            if let Err(e) = fs::remove_file(Path::new(dir(path).as_str()).join(s.name.as_str())) {
+                return Err(format!("failed to remove snapshot {}: {}", s.name, e));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the content of a snapshot.
+pub fn read(path: &str, name: &str) -> Result<String, String> {
+    if name.contains(['/', '\\']) || created_at(name).is_none() {
+        return Err(format!("{} is not a snapshot", name));
+    }
+    match fs::read_to_string(Path::new(dir(path).as_str()).join(name)) {
+        Ok(x) => Ok(x),
+        Err(e) => Err(format!("failed to read snapshot {}: {}", name, e)),
+    }
+}
+
+/// Replaces the content of a snapshot.
+pub fn write(path: &str, name: &str, content: &str) -> Result<(), String> {
+    match fs::write(Path::new(dir(path).as_str()).join(name), content) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("failed to write snapshot {}: {}", name, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_and_prune() {
+        let path =
+            std::env::temp_dir().join(format!("phonebook-snapshots-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, "1;John;Smith;;;;0;0").unwrap();
+        let day = 86400;
+        assert_eq!(
+            Some("19700102T000000Z.txt".to_string()),
+            take(path, day).unwrap()
+        );
+        assert_eq!(
+            Some("19700102T000000Z-2.txt".to_string()),
+            take(path, day).unwrap()
+        );
+        take(path, 3 * day).unwrap();
+        take(path, 4 * day).unwrap();
+        assert_eq!(4, list(path).unwrap().len());
+        assert_eq!(
+            "1;John;Smith;;;;0;0",
+            read(path, "19700104T000000Z.txt").unwrap()
+        );
+        assert!(read(path, "../passwd").is_err());
+
+        prune(path, Policy { count: 3, days: 0 }, 4 * day).unwrap();
+        let names: Vec<String> = list(path).unwrap().into_iter().map(|s| s.name).collect();
+        assert_eq!(
+            vec![
+                "19700102T000000Z-2.txt",
+                "19700104T000000Z.txt",
+                "19700105T000000Z.txt"
+            ],
+            names
+        );
+        prune(path, Policy { count: 3, days: 2 }, 5 * day).unwrap();
+        assert_eq!(2, list(path).unwrap().len());
+
+        fs::remove_dir_all(dir(path)).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use crate::entry::Entry;
+#[cfg(feature = "json")]
+use crate::json::{self, Line};
+use crate::meta::Metadata;
+
+/// Metadata key of the format version. Text storage writes it first, files
+/// without it are read as the older format that did not escape values.
+/// Version 2 stores all custom fields in one column.
+pub(crate) const FORMAT_KEY: &str = "phonebook";
+pub(crate) const FORMAT_VERSION: &str = "2";
+
+/// How a `FileDB` stores its entries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Storage {
+    /// `#key=value` metadata lines followed by one `;` separated entry per
+    /// line.
+    Text,
+    /// JSON Lines: a metadata object followed by one entry object per line.
+    #[cfg(feature = "json")]
+    JsonLines,
+}
+
+impl Storage {
+    /// Picks the storage by file extension, JSON Lines for `.jsonl` files.
+    pub fn for_path(path: &str) -> Result<Storage, String> {
+        if !path.ends_with(".jsonl") {
+            return Ok(Storage::Text);
+        }
+        #[cfg(feature = "json")]
+        return Ok(Storage::JsonLines);
+        #[cfg(not(feature = "json"))]
+        Err("JSON Lines databases need the json feature".to_string())
+    }
+
+    /// Reads metadata and entries from file content.
+    pub fn load(&self, content: &str) -> Result<(Metadata, Vec<Entry>), String> {
+        match self {
+            Storage::Text => {
+                let mut meta = Metadata::from_lines(content.split('\n'));
+                let parse: fn(&str) -> Result<Entry, String> = match meta.get(FORMAT_KEY) {
+                    Some(FORMAT_VERSION) => Entry::from,
+                    Some("1") => |s| Entry::from_older(s, true),
+                    None => |s| Entry::from_older(s, false),
+                    Some(v) => return Err(format!("unsupported phonebook format {}", v)),
+                };
+                meta.remove(FORMAT_KEY);
+                let entries: Vec<Entry> =
+                    content.split('\n').filter_map(|s| parse(s).ok()).collect();
+                Ok((meta, entries))
+            }
+            #[cfg(feature = "json")]
+            Storage::JsonLines => {
+                let mut meta = Metadata::default();
+                let mut entries: Vec<Entry> = Vec::new();
+                for (i, line) in content.lines().enumerate() {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match json::parse_line(line) {
+                        Ok(Line::Metadata(m)) => meta = m,
+                        Ok(Line::Entry(e)) => entries.push(*e),
+                        Err(e) => return Err(format!("line {}: {}", i + 1, e)),
+                    }
+                }
+                Ok((meta, entries))
+            }
+        }
+    }
+
+    /// Formats metadata and entries as file content.
+    pub fn dump(&self, meta: &Metadata, entries: &[Entry]) -> Result<String, String> {
+        match self {
+            Storage::Text => {
+                Ok(
+                    std::iter::once(format!("#{}={}", FORMAT_KEY, FORMAT_VERSION))
+                        .chain(meta.to_lines().into_iter().filter(|l| {
+                            Metadata::parse_line(l).is_none_or(|(k, _)| k != FORMAT_KEY)
+                        }))
+                        .chain(entries.iter().map(|e| e.to_string()))
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                        + "\n",
+                )
+            }
+            #[cfg(feature = "json")]
+            Storage::JsonLines => {
+                let mut lines = vec![json::metadata_line(meta)];
+                for e in entries.iter() {
+                    lines.push(json::entry_line(e)?);
+                }
+                Ok(lines.join("\n") + "\n")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_legacy_text_without_unescaping() {
+        let legacy =
+            "#region=US\n1;John;Smith;555 1234;C:\\Town\\Dir;;0;0\n2;Mary;Jones;;C:\\new;;0;0";
+        let (meta, entries) = Storage::Text.load(legacy).unwrap();
+        assert_eq!("C:\\Town\\Dir", entries[0].address);
+        assert_eq!("C:\\new", entries[1].address);
+
+        let content = Storage::Text.dump(&meta, &entries).unwrap();
+        assert!(content
+            .starts_with("#phonebook=2\n#region=US\n1;John;Smith;555 1234;C:\\\\Town\\\\Dir;"));
+        let (read_meta, read_entries) = Storage::Text.load(&content).unwrap();
+        assert_eq!(meta, read_meta);
+        assert_eq!("C:\\new", read_entries[1].address);
+
+        assert_eq!(
+            Err("unsupported phonebook format 3".to_string()),
+            Storage::Text.load("#phonebook=3").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn load_custom_field_columns_of_version_1() {
+        let v1 = "#phonebook=1\n1;John;Smith;;;;0;0;;;;;;;;nick=Johnny\\; J;url=x";
+        let (meta, entries) = Storage::Text.load(v1).unwrap();
+        assert_eq!(
+            Some(&"Johnny; J".to_string()),
+            entries[0].custom.get("nick")
+        );
+        assert_eq!(Some(&"x".to_string()), entries[0].custom.get("url"));
+
+        let content = Storage::Text.dump(&meta, &entries).unwrap();
+        assert_eq!(
+            "#phonebook=2\n1;John;Smith;;;;0;0;;;;;;;;nick=Johnny\\\\\\; J\\;url=x\n",
+            content
+        );
+        let (_, read) = Storage::Text.load(&content).unwrap();
+        assert_eq!(entries[0].custom, read[0].custom);
+    }
+}
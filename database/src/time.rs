@@ -0,0 +1,139 @@
+/// Date and time in UTC.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+const SECONDS_PER_DAY: u64 = 86400;
+
+impl DateTime {
+    /// Converts seconds since the Unix epoch.
+    pub fn from_timestamp(secs: u64) -> DateTime {
+        let (year, month, day) = civil_from_days((secs / SECONDS_PER_DAY) as i64);
+        let rest = (secs % SECONDS_PER_DAY) as u32;
+        DateTime {
+            year,
+            month,
+            day,
+            hour: rest / 3600,
+            minute: rest / 60 % 60,
+            second: rest % 60,
+        }
+    }
+
+    /// Converts to seconds since the Unix epoch, None for dates before it.
+    pub fn timestamp(&self) -> Option<u64> {
+        let days = days_from_civil(self.year, self.month, self.day);
+        if days < 0 {
+            return None;
+        }
+        Some(
+            days as u64 * SECONDS_PER_DAY
+                + (self.hour * 3600 + self.minute * 60 + self.second) as u64,
+        )
+    }
+
+    /// Parses an ISO 8601 date and time in UTC, in basic
+    /// (`19951031T222710Z`) or extended (`1995-10-31T22:27:10Z`) format.
+    pub fn parse(s: &str) -> Option<DateTime> {
+        let digits: String = s
+            .trim()
+            .trim_end_matches('Z')
+            .chars()
+            .filter(|c| *c != '-' && *c != ':')
+            .collect();
+        let (date, time) = digits
+            .split_once('T')
+            .unwrap_or((digits.as_str(), "000000"));
+        if date.len() != 8
+            || time.len() != 6
+            || !date.chars().chain(time.chars()).all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+        let number = |s: &str| s.parse::<u32>().ok();
+        let t = DateTime {
+            year: date[0..4].parse().ok()?,
+            month: number(&date[4..6])?,
+            day: number(&date[6..8])?,
+            hour: number(&time[0..2])?,
+            minute: number(&time[2..4])?,
+            second: number(&time[4..6])?,
+        };
+        let valid = (1..=12).contains(&t.month)
+            && (1..=31).contains(&t.day)
+            && t.hour < 24
+            && t.minute < 60
+            && t.second < 61;
+        match valid {
+            true => Some(t),
+            false => None,
+        }
+    }
+
+    /// Formats as ISO 8601 in basic format, e.g. `19951031T222710Z`.
+    pub fn to_basic(&self) -> String {
+        format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+
+    /// Formats as ISO 8601 in extended format, e.g. `1995-10-31T22:27:10Z`.
+    pub fn to_extended(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+// Conversions between days since 1970-01-01 and the proleptic Gregorian
+// calendar, see http://howardhinnant.github.io/date_algorithms.html
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_timestamps() {
+        let t = DateTime::from_timestamp(815178430);
+        assert_eq!("19951031T222710Z", t.to_basic());
+        assert_eq!("1995-10-31T22:27:10Z", t.to_extended());
+        assert_eq!(Some(815178430), t.timestamp());
+        assert_eq!(Some(t), DateTime::parse("1995-10-31T22:27:10Z"));
+        assert_eq!(
+            Some(951782400),
+            DateTime::parse("20000229").and_then(|t| t.timestamp())
+        );
+        assert_eq!(None, DateTime::parse("1995-13-01"));
+    }
+}
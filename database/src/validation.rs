@@ -0,0 +1,428 @@
+use crate::duplicates;
+use crate::entry::Entry;
+use crate::meta::Metadata;
+use crate::normalize::{self, MatchOptions};
+use crate::phone::{PhoneNumber, Region};
+use crate::query::Field;
+use std::fmt;
+
+/// Rule an entry does not satisfy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub field: Field,
+    pub message: String,
+}
+
+/// All rules an entry does not satisfy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violations(pub Vec<Violation>);
+
+impl fmt::Display for Violations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<&str> = self.0.iter().map(|v| v.message.as_str()).collect();
+        write!(f, "{}", messages.join("; "))
+    }
+}
+
+/// Entry already holding a value that must be unique.
+#[derive(Clone)]
+pub struct Conflict {
+    pub field: Field,
+    /// ID of the entry already holding the value.
+    pub id: u16,
+    /// Full name of the entry already holding the value.
+    pub name: String,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} must be unique but is already used by entry {} ({})",
+            self.field.label(),
+            self.id,
+            self.name
+        )
+    }
+}
+
+/// Rules every entry of a phonebook has to satisfy.
+///
+/// Rules are stored in the phonebook's metadata under the keys `required`
+/// (list of fields), `max-length` (list of `field:length`), `check-email`,
+/// `check-phone` (`true` or `false`), `forbidden-chars` and `unique` (list
+/// of fields no two entries may share).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rules {
+    /// Fields that must not be empty.
+    pub required: Vec<Field>,
+    /// Maximum number of characters of a field.
+    pub max_lengths: Vec<(Field, usize)>,
+    /// Check that the e-mail address, if given, looks like one.
+    pub check_email: bool,
+    /// Check that the phone number, if given, is a valid number.
+    pub check_phone: bool,
+    /// Characters no field may contain.
+    pub forbidden_chars: String,
+    /// Fields no two entries may have the same value in. Empty values are
+    /// not compared.
+    pub unique: Vec<Field>,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            required: vec![Field::FirstName, Field::LastName, Field::Phone],
+            max_lengths: Vec::new(),
+            check_email: true,
+            check_phone: true,
+            forbidden_chars: "".to_string(),
+            unique: Vec::new(),
+        }
+    }
+}
+
+// fields rules can be set on
+const FIELDS: [Field; 10] = [
+    Field::FirstName,
+    Field::LastName,
+    Field::Phone,
+    Field::Address,
+    Field::Email,
+    Field::Tag,
+    Field::Organization,
+    Field::Note,
+    Field::Birthday,
+    Field::Title,
+];
+
+fn is_empty(value: &str) -> bool {
+    // "--" is how the CLI marks a value as cleared
+    value.trim().is_empty() || value == "--"
+}
+
+fn parse_rule_field(s: &str) -> Result<Field, String> {
+    let field = Field::from(s.trim())?;
+    if !FIELDS.contains(&field) {
+        return Err(format!("rules can not be set on field {}", s));
+    }
+    Ok(field)
+}
+
+fn parse_unique_field(s: &str) -> Result<Field, String> {
+    let field = Field::from(s.trim())?;
+    if field == Field::Name || (FIELDS.contains(&field) && field != Field::Tag) {
+        return Ok(field);
+    }
+    Err(format!("field {} can not be unique", s))
+}
+
+fn parse_bool(key: &str, s: &str) -> Result<bool, String> {
+    match s {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("{} must be true or false", key)),
+    }
+}
+
+impl Rules {
+    /// Reads rules from metadata, using the default for missing keys.
+    pub fn from_metadata(meta: &Metadata) -> Result<Rules, String> {
+        let mut rules = Rules::default();
+        if let Some(s) = meta.get("required") {
+            rules.required = s
+                .split(',')
+                .filter(|f| !f.trim().is_empty())
+                .map(parse_rule_field)
+                .collect::<Result<Vec<Field>, String>>()?;
+        }
+        if let Some(s) = meta.get("max-length") {
+            rules.max_lengths = Vec::new();
+            for part in s.split(',').filter(|p| !p.trim().is_empty()) {
+                let (field, len) = match part.split_once(':') {
+                    Some(x) => x,
+                    None => {
+                        return Err(format!("max-length {} must be given as field:length", part))
+                    }
+                };
+                let len = match len.trim().parse::<usize>() {
+                    Ok(x) => x,
+                    Err(e) => {
+                        return Err(format!("failed to parse max-length of {}: {}", field, e))
+                    }
+                };
+                rules.max_lengths.push((parse_rule_field(field)?, len));
+            }
+        }
+        if let Some(s) = meta.get("check-email") {
+            rules.check_email = parse_bool("check-email", s)?;
+        }
+        if let Some(s) = meta.get("check-phone") {
+            rules.check_phone = parse_bool("check-phone", s)?;
+        }
+        if let Some(s) = meta.get("forbidden-chars") {
+            rules.forbidden_chars = s.to_string();
+        }
+        if let Some(s) = meta.get("unique") {
+            rules.unique = s
+                .split(',')
+                .filter(|f| !f.trim().is_empty())
+                .map(parse_unique_field)
+                .collect::<Result<Vec<Field>, String>>()?;
+        }
+        Ok(rules)
+    }
+
+    pub fn to_metadata(&self, meta: &mut Metadata) {
+        let required: Vec<&str> = self.required.iter().map(|f| f.key()).collect();
+        meta.set("required", required.join(",").as_str());
+        let max_lengths: Vec<String> = self
+            .max_lengths
+            .iter()
+            .map(|(f, len)| format!("{}:{}", f.key(), len))
+            .collect();
+        meta.set("max-length", max_lengths.join(",").as_str());
+        meta.set("check-email", self.check_email.to_string().as_str());
+        meta.set("check-phone", self.check_phone.to_string().as_str());
+        meta.set("forbidden-chars", self.forbidden_chars.as_str());
+        let unique: Vec<&str> = self.unique.iter().map(|f| f.key()).collect();
+        meta.set("unique", unique.join(",").as_str());
+    }
+
+    /// Checks that an entry does not share a unique field with any of
+    /// `entries`, returning the first entry it conflicts with. Entries with
+    /// the ID of `e` are skipped, so an entry does not conflict with itself.
+    pub fn check_unique<'a, I>(&self, e: &Entry, entries: I) -> Result<(), Conflict>
+    where
+        I: Iterator<Item = &'a Entry>,
+    {
+        if self.unique.is_empty() {
+            return Ok(());
+        }
+        for existing in entries.filter(|x| x.id != e.id) {
+            if let Some(field) = self.unique.iter().find(|f| same_value(**f, e, existing)) {
+                return Err(Conflict {
+                    field: *field,
+                    id: existing.id,
+                    name: format!("{} {}", existing.first_name, existing.last_name),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks an entry against all rules, reporting every violation.
+    /// Phone numbers without country code are checked against `region`.
+    pub fn validate(&self, e: &Entry, region: Option<&Region>) -> Result<(), Violations> {
+        let mut violations: Vec<Violation> = Vec::new();
+        let mut violation =
+            |field: Field, message: String| violations.push(Violation { field, message });
+        for field in FIELDS.iter() {
+            let value = e.get(*field);
+            if self.required.contains(field) && is_empty(&value) {
+                violation(*field, format!("{} must be stated", field.label()));
+                continue;
+            }
+            if let Some((_, max)) = self.max_lengths.iter().find(|(f, _)| f == field) {
+                if value.chars().count() > *max {
+                    violation(
+                        *field,
+                        format!(
+                            "{} must not be longer than {} characters",
+                            field.label(),
+                            max
+                        ),
+                    );
+                }
+            }
+            if let Some(c) = value.chars().find(|c| self.forbidden_chars.contains(*c)) {
+                violation(
+                    *field,
+                    format!("{} must not contain '{}'", field.label(), c),
+                );
+            }
+        }
+        if self.check_email && !is_empty(&e.e_mail) && !is_valid_email(&e.e_mail) {
+            violation(
+                Field::Email,
+                format!("{} is not a valid e-mail address", e.e_mail),
+            );
+        }
+        if self.check_phone && !is_empty(&e.phone) {
+            if let Err(msg) = PhoneNumber::parse(&e.phone, region) {
+                violation(
+                    Field::Phone,
+                    format!("invalid phone number {}: {}", e.phone, msg),
+                );
+            }
+        }
+        match violations.len() {
+            0 => Ok(()),
+            _ => Err(Violations(violations)),
+        }
+    }
+}
+
+/// Compares a unique field of two entries. Phone numbers are compared by
+/// their digits, everything else ignoring case and accents.
+fn same_value(field: Field, a: &Entry, b: &Entry) -> bool {
+    let (x, y) = match field {
+        Field::Phone => return !is_empty(&a.phone) && duplicates::same_phone(a, b),
+        _ => (a.get(field), b.get(field)),
+    };
+    !is_empty(x.trim()) && normalize::equals(x.trim(), y.trim(), MatchOptions::default())
+}
+
+/// Checks the syntax of an e-mail address: a local part, `@` and a domain
+/// of at least two labels made of letters, digits and hyphens.
+pub fn is_valid_email(s: &str) -> bool {
+    let (local, domain) = match s.rsplit_once('@') {
+        Some(x) => x,
+        None => return false,
+    };
+    if local.is_empty()
+        || local.len() > 64
+        || local.starts_with('.')
+        || local.ends_with('.')
+        || local.contains("..")
+    {
+        return false;
+    }
+    if !local
+        .chars()
+        .all(|c| c.is_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c))
+    {
+        return false;
+    }
+    let labels: Vec<&str> = domain.split('.').collect();
+    labels.len() >= 2
+        && labels.iter().all(|l| {
+            !l.is_empty()
+                && !l.starts_with('-')
+                && !l.ends_with('-')
+                && l.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            first_name: "John".to_string(),
+            last_name: "Smith".to_string(),
+            phone: "+1 555 123 4567".to_string(),
+            address: "Wild west".to_string(),
+            e_mail: "js@gmail.com".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_entry() {
+        assert_eq!(Ok(()), Rules::default().validate(&entry(), None));
+    }
+
+    #[test]
+    fn missing_first_name() {
+        let mut e = entry();
+        e.first_name = "".to_string();
+        match Rules::default().validate(&e, None) {
+            Ok(()) => panic!("This should fail!"),
+            Err(v) => assert_eq!("first name must be stated".to_string(), v.to_string()),
+        }
+    }
+
+    #[test]
+    fn all_violations_are_reported() {
+        let mut rules = Rules::default();
+        rules.max_lengths.push((Field::Address, 5));
+        rules.forbidden_chars = "<>".to_string();
+        let e = Entry {
+            first_name: "--".to_string(),
+            last_name: "<b>".to_string(),
+            phone: "12ab".to_string(),
+            address: "Wild west".to_string(),
+            e_mail: "js@gmail".to_string(),
+            ..Default::default()
+        };
+        let fields: Vec<Field> = rules
+            .validate(&e, None)
+            .unwrap_err()
+            .0
+            .iter()
+            .map(|v| v.field)
+            .collect();
+        assert_eq!(
+            vec![
+                Field::FirstName,
+                Field::LastName,
+                Field::Address,
+                Field::Email,
+                Field::Phone
+            ],
+            fields
+        );
+    }
+
+    #[test]
+    fn rules_round_trip_through_metadata() {
+        let mut meta = Metadata::default();
+        meta.set("required", "first,email");
+        meta.set("max-length", "first:20, address:100");
+        meta.set("check-phone", "false");
+        meta.set("unique", "email, name");
+        let rules = Rules::from_metadata(&meta).unwrap();
+        assert_eq!(vec![Field::FirstName, Field::Email], rules.required);
+        assert_eq!(
+            vec![(Field::FirstName, 20), (Field::Address, 100)],
+            rules.max_lengths
+        );
+        assert!(rules.check_email);
+        assert!(!rules.check_phone);
+        assert_eq!(vec![Field::Email, Field::Name], rules.unique);
+        let mut written = Metadata::default();
+        rules.to_metadata(&mut written);
+        assert_eq!(Ok(rules), Rules::from_metadata(&written));
+        meta.set("required", "id");
+        assert!(Rules::from_metadata(&meta).is_err());
+    }
+
+    #[test]
+    fn unique_fields() {
+        let mut rules = Rules::default();
+        let mut existing = entry();
+        existing.id = 3;
+        let existing = [existing];
+        let mut e = entry();
+        e.phone = "(555) 123-4567".to_string();
+        e.e_mail = "JS@gmail.com".to_string();
+        assert!(rules.check_unique(&e, existing.iter()).is_ok());
+        rules.unique = vec![Field::Email, Field::Phone];
+        let conflict = rules.check_unique(&e, existing.iter()).err().unwrap();
+        assert_eq!(Field::Email, conflict.field);
+        assert_eq!(
+            "e-mail must be unique but is already used by entry 3 (John Smith)",
+            conflict.to_string()
+        );
+        e.e_mail = "".to_string();
+        assert_eq!(
+            Field::Phone,
+            rules.check_unique(&e, existing.iter()).err().unwrap().field
+        );
+        // an entry does not conflict with itself
+        e.id = 3;
+        assert!(rules.check_unique(&e, existing.iter()).is_ok());
+    }
+
+    #[test]
+    fn email_syntax() {
+        assert!(is_valid_email("john.smith+work@mail.example.com"));
+        assert!(!is_valid_email("john"));
+        assert!(!is_valid_email("john@localhost"));
+        assert!(!is_valid_email("john@@example.com"));
+        assert!(!is_valid_email("john smith@example.com"));
+        assert!(!is_valid_email("john@-example.com"));
+    }
+}
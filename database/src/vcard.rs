@@ -0,0 +1,509 @@
+use crate::entry::Entry;
+use crate::import::RowError;
+use crate::query::Field;
+use crate::time::DateTime;
+
+/// vCard version written on export. Both are read on import.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Version {
+    V3,
+    V4,
+}
+
+impl Version {
+    fn number(&self) -> &'static str {
+        match self {
+            Version::V3 => "3.0",
+            Version::V4 => "4.0",
+        }
+    }
+}
+
+/// Property of a vCard, e.g. `TEL;TYPE=cell:+1 555 123 4567`.
+#[derive(Clone, Debug, PartialEq)]
+struct Property {
+    /// Name in upper case, without group.
+    name: String,
+    /// Parameters with upper case names. Values are not unescaped.
+    params: Vec<(String, String)>,
+    /// Raw value, still escaped.
+    value: String,
+}
+
+impl Property {
+    /// Returns true for properties marked as preferred, by `TYPE=pref` in
+    /// vCard 3.0 or `PREF=1` in vCard 4.0.
+    fn preferred(&self) -> bool {
+        self.params.iter().any(|(k, v)| match k.as_str() {
+            "TYPE" => v.split(',').any(|t| t.eq_ignore_ascii_case("pref")),
+            "PREF" => v.trim() == "1",
+            _ => false,
+        })
+    }
+}
+
+/// Joins folded lines, which continue on the next line starting with a
+/// space or tab. Returns the unfolded lines with the line they start on.
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (i, line) in text.split('\n').enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.1.push_str(rest),
+            _ => lines.push((i + 1, line.to_string())),
+        }
+    }
+    lines.retain(|(_, l)| !l.trim().is_empty());
+    lines
+}
+
+/// Parses a content line into its name, parameters and value.
+fn parse_property(line: &str) -> Result<Property, String> {
+    // the value starts at the first colon outside of quoted parameters
+    let mut quoted = false;
+    let colon = line.char_indices().find(|(_, c)| {
+        if *c == '"' {
+            quoted = !quoted;
+        }
+        *c == ':' && !quoted
+    });
+    let (head, value) = match colon {
+        Some((i, _)) => (&line[..i], &line[i + 1..]),
+        None => return Err(format!("line {} has no value", line)),
+    };
+    let mut parts = head.split(';');
+    let name = parts.next().unwrap_or("");
+    // a group prefix such as item1.TEL is dropped
+    let name = name
+        .rsplit('.')
+        .next()
+        .unwrap_or(name)
+        .trim()
+        .to_uppercase();
+    if name.is_empty() {
+        return Err(format!("line {} has no property name", line));
+    }
+    let params = parts
+        .map(|p| match p.split_once('=') {
+            Some((k, v)) => (k.trim().to_uppercase(), v.trim_matches('"').to_string()),
+            // vCard 2.1 allows types without TYPE=, e.g. TEL;CELL
+            None => ("TYPE".to_string(), p.to_string()),
+        })
+        .collect();
+    Ok(Property {
+        name,
+        params,
+        value: value.to_string(),
+    })
+}
+
+/// Unescapes a text value.
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => result.push('\n'),
+                Some(x) => result.push(x),
+                None => result.push('\\'),
+            },
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Splits a structured value such as N or ADR at unescaped semicolons and
+/// unescapes the components.
+fn components(s: &str) -> Vec<String> {
+    let mut parts: Vec<String> = Vec::new();
+    let mut part = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                part.push(c);
+                if let Some(x) = chars.next() {
+                    part.push(x);
+                }
+            }
+            ';' => parts.push(unescape(&std::mem::take(&mut part))),
+            _ => part.push(c),
+        }
+    }
+    parts.push(unescape(&part));
+    parts
+}
+
+/// Splits a list value such as CATEGORIES at unescaped commas.
+fn list(s: &str) -> Vec<String> {
+    let mut items: Vec<String> = Vec::new();
+    let mut item = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                item.push(c);
+                if let Some(x) = chars.next() {
+                    item.push(x);
+                }
+            }
+            ',' => items.push(unescape(&std::mem::take(&mut item))),
+            _ => item.push(c),
+        }
+    }
+    items.push(unescape(&item));
+    items
+        .into_iter()
+        .map(|i| i.trim().to_string())
+        .filter(|i| !i.is_empty())
+        .collect()
+}
+
+/// Brings a birthday to `YYYY-MM-DD` or `--MM-DD` form where it is given in
+/// basic format, keeping other values as they are.
+fn birthday(s: &str) -> String {
+    let s = s.trim();
+    let date = s.split('T').next().unwrap_or(s);
+    let all_digits = |x: &str| x.chars().all(|c| c.is_ascii_digit());
+    if date.len() == 8 && all_digits(date) {
+        return format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8]);
+    }
+    if date.len() == 6 && date.starts_with("--") && all_digits(&date[2..]) {
+        return format!("--{}-{}", &date[2..4], &date[4..6]);
+    }
+    date.to_string()
+}
+
+/// Builds an entry from the properties of one card.
+fn card_entry(props: &[Property]) -> Result<Entry, String> {
+    let mut e = Entry::default();
+    // the preferred value, or the first one
+    let pick = |name: &str| {
+        let mut candidates = props
+            .iter()
+            .filter(|p| p.name == name && !p.value.trim().is_empty());
+        let first = candidates.clone().next();
+        candidates.find(|p| p.preferred()).or(first)
+    };
+    if let Some(p) = pick("N") {
+        let n = components(&p.value);
+        e.last_name = n.first().cloned().unwrap_or_default().trim().to_string();
+        e.first_name = n.get(1).cloned().unwrap_or_default().trim().to_string();
+    }
+    if e.first_name.is_empty() && e.last_name.is_empty() {
+        match pick("FN") {
+            Some(p) => e.set(Field::Name, unescape(&p.value).as_str()),
+            None => return Err("card has no name".to_string()),
+        }
+    }
+    if let Some(p) = pick("TEL") {
+        let value = unescape(&p.value);
+        e.phone = value.trim().trim_start_matches("tel:").to_string();
+    }
+    if let Some(p) = pick("EMAIL") {
+        e.e_mail = unescape(&p.value).trim().to_string();
+    }
+    if let Some(p) = pick("ADR") {
+        let parts: Vec<String> = components(&p.value)
+            .into_iter()
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect();
+        e.address = parts.join(", ");
+    }
+    if let Some(p) = pick("ORG") {
+        let parts: Vec<String> = components(&p.value)
+            .into_iter()
+            .map(|c| c.trim().to_string())
+            .filter(|c| !c.is_empty())
+            .collect();
+        e.organization = parts.join(", ");
+    }
+    if let Some(p) = pick("TITLE") {
+        e.title = unescape(&p.value);
+    }
+    if let Some(p) = pick("NOTE") {
+        e.note = unescape(&p.value);
+    }
+    if let Some(p) = pick("BDAY") {
+        e.birthday = birthday(&unescape(&p.value));
+    }
+    if let Some(p) = pick("UID") {
+        e.uid = unescape(&p.value).trim().to_string();
+    }
+    if let Some(p) = pick("REV") {
+        e.updated_at = DateTime::parse(&p.value)
+            .and_then(|t| t.timestamp())
+            .unwrap_or(0);
+    }
+    for p in props.iter().filter(|p| p.name == "CATEGORIES") {
+        for tag in list(&p.value) {
+            if !e.tags.contains(&tag) {
+                e.tags.push(tag);
+            }
+        }
+    }
+    Ok(e)
+}
+
+/// Reads all cards of a vCard file. Cards that can not be read are reported
+/// with the line they start on, without stopping the import.
+pub fn read_entries(text: &str) -> (Vec<(usize, Entry)>, Vec<RowError>) {
+    let mut entries: Vec<(usize, Entry)> = Vec::new();
+    let mut errors: Vec<RowError> = Vec::new();
+    // line the current card starts on, the properties read so far and the
+    // first error in it
+    let mut card: Option<(usize, Vec<Property>, Option<String>)> = None;
+    for (line, content) in unfold(text) {
+        let upper = content.trim().to_uppercase();
+        if upper == "BEGIN:VCARD" {
+            if let Some((start, _, _)) = card.take() {
+                errors.push(RowError {
+                    line: start,
+                    message: "card has no END:VCARD".to_string(),
+                });
+            }
+            card = Some((line, Vec::new(), None));
+            continue;
+        }
+        let (start, props, error) = match card.as_mut() {
+            Some(x) => x,
+            None => {
+                errors.push(RowError {
+                    line,
+                    message: "content outside of BEGIN:VCARD and END:VCARD".to_string(),
+                });
+                continue;
+            }
+        };
+        if upper == "END:VCARD" {
+            let result = match error.take() {
+                Some(message) => Err(message),
+                None => card_entry(props),
+            };
+            match result {
+                Ok(e) => entries.push((*start, e)),
+                Err(message) => errors.push(RowError {
+                    line: *start,
+                    message,
+                }),
+            }
+            card = None;
+            continue;
+        }
+        match parse_property(&content) {
+            Ok(p) => props.push(p),
+            Err(message) => {
+                if error.is_none() {
+                    *error = Some(format!("{} (line {})", message, line));
+                }
+            }
+        }
+    }
+    if let Some((start, _, _)) = card {
+        errors.push(RowError {
+            line: start,
+            message: "card has no END:VCARD".to_string(),
+        });
+    }
+    // unterminated cards are found after the entries following them
+    errors.sort_by_key(|e| e.line);
+    (entries, errors)
+}
+
+/// Escapes a text value. Semicolons are escaped too, as required for the
+/// components of structured values.
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            ',' => result.push_str("\\,"),
+            ';' => result.push_str("\\;"),
+            '\n' => result.push_str("\\n"),
+            '\r' => (),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Folds a content line into lines of at most 75 bytes, never splitting a
+/// character, and ends it with CRLF.
+fn fold(line: &str) -> String {
+    let mut result = String::with_capacity(line.len() + 8);
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            result.push_str("\r\n ");
+            // the leading space counts towards the line length
+            width = 1;
+        }
+        result.push(c);
+        width += c.len_utf8();
+    }
+    result.push_str("\r\n");
+    result
+}
+
+/// Writes entries as one card each.
+pub fn write_entries(entries: &[Entry], version: Version) -> String {
+    let mut result = String::new();
+    for e in entries.iter() {
+        let mut lines: Vec<String> = vec![
+            "BEGIN:VCARD".to_string(),
+            format!("VERSION:{}", version.number()),
+        ];
+        if !e.uid.is_empty() {
+            lines.push(format!("UID:{}", escape(&e.uid)));
+        }
+        let full_name = format!("{} {}", e.first_name, e.last_name);
+        lines.push(format!("FN:{}", escape(full_name.trim())));
+        lines.push(format!(
+            "N:{};{};;;",
+            escape(&e.last_name),
+            escape(&e.first_name)
+        ));
+        if !e.phone.is_empty() {
+            let param = match version {
+                Version::V3 => "TYPE=voice",
+                Version::V4 => "VALUE=text",
+            };
+            lines.push(format!("TEL;{}:{}", param, escape(&e.phone)));
+        }
+        if !e.e_mail.is_empty() {
+            lines.push(format!("EMAIL:{}", escape(&e.e_mail)));
+        }
+        if !e.address.is_empty() {
+            // the address is not split into its parts, all of it is the street
+            lines.push(format!("ADR:;;{};;;;", escape(&e.address)));
+        }
+        if !e.organization.is_empty() {
+            lines.push(format!("ORG:{}", escape(&e.organization)));
+        }
+        if !e.title.is_empty() {
+            lines.push(format!("TITLE:{}", escape(&e.title)));
+        }
+        if !e.note.is_empty() {
+            lines.push(format!("NOTE:{}", escape(&e.note)));
+        }
+        if !e.birthday.is_empty() {
+            lines.push(format!("BDAY:{}", e.birthday));
+        }
+        if !e.tags.is_empty() {
+            let tags: Vec<String> = e.tags.iter().map(|t| escape(t)).collect();
+            lines.push(format!("CATEGORIES:{}", tags.join(",")));
+        }
+        if e.updated_at > 0 {
+            lines.push(format!(
+                "REV:{}",
+                DateTime::from_timestamp(e.updated_at).to_basic()
+            ));
+        }
+        lines.push("END:VCARD".to_string());
+        for line in lines.iter() {
+            result.push_str(fold(line).as_str());
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_cards() {
+        let text = "BEGIN:VCARD\r\n\
+            VERSION:3.0\r\n\
+            N:Smith;John;;;\r\n\
+            FN:John Smith\r\n\
+            TEL;TYPE=work:+1 555 000 1111\r\n\
+            item1.TEL;TYPE=cell,pref:+1 555 123 4567\r\n\
+            EMAIL;TYPE=internet:john@example.com\r\n\
+            ADR;TYPE=home:;;Main street 1;Springfield;;12345;USA\r\n\
+            ORG:ACME\\, Inc.;Sales\r\n\
+            NOTE:Met at the fair\\nCall in the morning; after 9 \r\n \\, please\r\n\
+            BDAY:19850412\r\n\
+            CATEGORIES:work,friends\r\n\
+            UID:urn:uuid:4fbe8971-0bc3-424c-9c26-36c3e1eff6b1\r\n\
+            REV:19951031T222710Z\r\n\
+            END:VCARD\r\n\
+            BEGIN:VCARD\r\n\
+            VERSION:4.0\r\n\
+            FN:Mary Ann Jones\r\n\
+            TEL;VALUE=uri;PREF=1:tel:+44-20-7946-0018\r\n\
+            END:VCARD\r\n";
+        let (entries, errors) = read_entries(text);
+        assert!(errors.is_empty());
+        assert_eq!(2, entries.len());
+        let (line, e) = &entries[0];
+        assert_eq!(1, *line);
+        assert_eq!("John", e.first_name);
+        assert_eq!("Smith", e.last_name);
+        assert_eq!("+1 555 123 4567", e.phone);
+        assert_eq!("john@example.com", e.e_mail);
+        assert_eq!("Main street 1, Springfield, 12345, USA", e.address);
+        assert_eq!("ACME, Inc., Sales", e.organization);
+        assert_eq!(
+            "Met at the fair\nCall in the morning; after 9 , please",
+            e.note
+        );
+        assert_eq!("1985-04-12", e.birthday);
+        assert_eq!(vec!["work", "friends"], e.tags);
+        assert_eq!("urn:uuid:4fbe8971-0bc3-424c-9c26-36c3e1eff6b1", e.uid);
+        assert_eq!(815178430, e.updated_at);
+        let (line, e) = &entries[1];
+        assert_eq!(17, *line);
+        assert_eq!("Mary", e.first_name);
+        assert_eq!("Ann Jones", e.last_name);
+        assert_eq!("+44-20-7946-0018", e.phone);
+    }
+
+    #[test]
+    fn report_broken_cards() {
+        let text = "BEGIN:VCARD\nVERSION:3.0\nEMAIL:x@example.com\nEND:VCARD\n\
+            BEGIN:VCARD\nFN:John\nbroken line\nEND:VCARD\n\
+            BEGIN:VCARD\nFN:Mary\n";
+        let (entries, errors) = read_entries(text);
+        assert!(entries.is_empty());
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            vec![
+                "line 1: card has no name",
+                "line 5: line broken line has no value (line 7)",
+                "line 9: card has no END:VCARD"
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let e = Entry {
+            first_name: "Zoë".to_string(),
+            last_name: "O'Brien; Jr.".to_string(),
+            phone: "+1 555 123 4567".to_string(),
+            address: "Main street 1, Springfield".to_string(),
+            note: "A long note that does not fit on a single line of a vCard and has to be folded, ünïcödé".to_string(),
+            tags: vec!["work".to_string()],
+            updated_at: 815178430,
+            ..Default::default()
+        };
+        let text = write_entries(std::slice::from_ref(&e), Version::V4);
+        assert!(text.contains("N:O'Brien\\; Jr.;Zoë;;;\r\n"));
+        assert!(text.contains("REV:19951031T222710Z\r\n"));
+        assert!(text.lines().all(|l| l.len() <= 75));
+        let (entries, errors) = read_entries(&text);
+        assert!(errors.is_empty());
+        let read = &entries[0].1;
+        assert_eq!(e.first_name, read.first_name);
+        assert_eq!(e.last_name, read.last_name);
+        assert_eq!(e.phone, read.phone);
+        assert_eq!(e.address, read.address);
+        assert_eq!(e.note, read.note);
+        assert_eq!(e.tags, read.tags);
+    }
+}
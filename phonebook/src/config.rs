@@ -0,0 +1,149 @@
+use crate::Output;
+use database::{Field, PhoneFormat};
+use std::env;
+use std::fs;
+use toml::{Table, Value};
+
+/// System wide configuration file.
+const SYSTEM_PATH: &str = "/etc/phonebook/config.toml";
+
+/// Settings read from configuration files. Unset values are None.
+#[derive(Default)]
+pub struct Config {
+    /// Path of the phonebook used unless `-d` is given.
+    pub database: Option<String>,
+    /// Region of phone numbers for phonebooks without one of their own.
+    pub region: Option<String>,
+    pub phone_format: Option<PhoneFormat>,
+    pub output: Option<Output>,
+    /// Columns of printed tables.
+    pub columns: Option<Vec<Field>>,
+    pub redact: Option<bool>,
+}
+
+/// Expands a leading `~/` to the home directory.
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home.trim_end_matches('/'), rest),
+        _ => path.to_string(),
+    }
+}
+
+fn string(key: &str, value: &Value) -> Result<String, String> {
+    match value.as_str() {
+        Some(s) => Ok(s.to_string()),
+        None => Err(format!("{} must be a string", key)),
+    }
+}
+
+impl Config {
+    /// Reads the system configuration, then the user's from
+    /// `$XDG_CONFIG_HOME/phonebook/config.toml` (`~/.config` if not set) and
+    /// then the file named by `PHONEBOOK_CONFIG`, later files overriding
+    /// earlier ones. Missing files are skipped, except `PHONEBOOK_CONFIG`.
+    pub fn load() -> Result<Config, String> {
+        let mut config = Config::default();
+        let user_dir = match (env::var("XDG_CONFIG_HOME"), env::var("HOME")) {
+            (Ok(dir), _) if !dir.is_empty() => Some(dir),
+            (_, Ok(home)) => Some(format!("{}/.config", home)),
+            _ => None,
+        };
+        let mut paths = vec![SYSTEM_PATH.to_string()];
+        if let Some(dir) = user_dir {
+            paths.push(format!("{}/phonebook/config.toml", dir));
+        }
+        for path in paths.iter() {
+            if let Ok(text) = fs::read_to_string(path) {
+                config.merge(Config::parse(text.as_str()).map_err(|e| format!("{}: {}", path, e))?);
+            }
+        }
+        if let Ok(path) = env::var("PHONEBOOK_CONFIG") {
+            let text = match fs::read_to_string(path.as_str()) {
+                Ok(x) => x,
+                Err(e) => return Err(format!("failed to read {}: {}", path, e)),
+            };
+            config.merge(Config::parse(text.as_str()).map_err(|e| format!("{}: {}", path, e))?);
+        }
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let table: Table = match text.parse() {
+            Ok(x) => x,
+            Err(e) => return Err(e.to_string().trim().to_string()),
+        };
+        let mut config = Config::default();
+        for (key, value) in table.iter() {
+            match key.as_str() {
+                "database" => config.database = Some(expand_home(string(key, value)?.as_str())),
+                "region" => config.region = Some(string(key, value)?),
+                "phone_format" => {
+                    config.phone_format = Some(PhoneFormat::from(string(key, value)?.as_str())?)
+                }
+                "output" => config.output = Some(Output::from(string(key, value)?.as_str())?),
+                "columns" => {
+                    let names = match value.as_array() {
+                        Some(x) => x,
+                        None => return Err("columns must be an array".to_string()),
+                    };
+                    let mut columns: Vec<Field> = Vec::new();
+                    for name in names.iter() {
+                        match Field::from(string(key, name)?.as_str())? {
+                            Field::Any => return Err("any is not a column".to_string()),
+                            f => columns.push(f),
+                        }
+                    }
+                    config.columns = Some(columns);
+                }
+                "redact" => match value.as_bool() {
+                    Some(x) => config.redact = Some(x),
+                    None => return Err("redact must be true or false".to_string()),
+                },
+                _ => return Err(format!("unknown setting {}", key)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Takes the values set in `other`.
+    fn merge(&mut self, other: Config) {
+        self.database = other.database.or(self.database.take());
+        self.region = other.region.or(self.region.take());
+        self.phone_format = other.phone_format.or(self.phone_format.take());
+        self.output = other.output.or(self.output.take());
+        self.columns = other.columns.or(self.columns.take());
+        self.redact = other.redact.or(self.redact.take());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_merge() {
+        let mut config = Config::parse(
+            "database = \"/data/phonebook.txt\"\n\
+            region = \"GB\"\n\
+            columns = [\"id\", \"name\", \"phone\"]\n",
+        )
+        .unwrap();
+        let user =
+            Config::parse("region = \"US\"\nphone_format = \"international\"\nredact = true\n")
+                .unwrap();
+        config.merge(user);
+        assert_eq!(Some("/data/phonebook.txt".to_string()), config.database);
+        assert_eq!(Some("US".to_string()), config.region);
+        assert!(config.phone_format == Some(PhoneFormat::International));
+        assert!(config.columns == Some(vec![Field::Id, Field::Name, Field::Phone]));
+        assert_eq!(Some(true), config.redact);
+        assert_eq!(
+            Err("unknown setting colour".to_string()),
+            Config::parse("colour = \"red\"").map(|_| ())
+        );
+        assert_eq!(
+            Err("region must be a string".to_string()),
+            Config::parse("region = 1").map(|_| ())
+        );
+    }
+}
@@ -1,14 +1,210 @@
-use database::{Entry, FileDB, DB};
+use config::Config;
+use database::csv::{self, CsvOptions};
+#[cfg(feature = "json")]
+use database::json;
+use database::snapshot;
+use database::vcard::{self, Version};
+use database::{abook, ldif, mutt};
+use database::{
+    decode, is_encrypted, DateTime, Duplicate, Encoding, Entry, Field, FileDB, Key, Match,
+    MatchOptions, Metadata, PhoneFormat, Profile, Query, Redaction, RowError, Subject, DB,
+};
+use std::cmp::Ordering;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+
+mod config;
 
 enum Operation {
     Create,
     Update,
     Delete,
     Search,
+    Lookup,
+    Duplicates,
+    Merge,
+    Import,
+    Export,
+    Query,
+    Backup,
+    Restore,
+    Snapshots,
+    Encrypt,
+    ChangePassphrase,
+    Decrypt,
+    Gdpr,
+    Init,
+    List,
+    Show,
     Help,
     None,
 }
 
+/// What to do when a new entry has the phone number or e-mail of an
+/// existing one.
+#[derive(Clone, Copy, PartialEq)]
+enum OnDuplicate {
+    /// Create the entry and print a warning.
+    Warn,
+    /// Do not create the entry.
+    Skip,
+    /// Do not create the entry and report an error.
+    Fail,
+    /// Create the entry without checking.
+    Create,
+    /// Ask whether to create the entry.
+    Prompt,
+}
+
+impl OnDuplicate {
+    fn from(s: &str) -> Result<OnDuplicate, String> {
+        match s {
+            "warn" => Ok(OnDuplicate::Warn),
+            "skip" => Ok(OnDuplicate::Skip),
+            "fail" => Ok(OnDuplicate::Fail),
+            "create" => Ok(OnDuplicate::Create),
+            "prompt" => Ok(OnDuplicate::Prompt),
+            _ => Err(format!("unknown value {} of on-duplicate", s)),
+        }
+    }
+}
+
+/// Format of imported and exported files.
+#[derive(Clone, Copy, PartialEq)]
+enum FileFormat {
+    Csv,
+    /// vCard 4.0, or 3.0 for programs not supporting it.
+    Vcard(Version),
+    /// LDIF with inetOrgPerson records.
+    Ldif,
+    /// abook addressbook file.
+    Abook,
+    /// mutt alias file.
+    Mutt,
+    /// Array of entries with the field names of `Entry`, export only.
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl FileFormat {
+    fn from(s: &str) -> Result<FileFormat, String> {
+        match s {
+            "csv" => Ok(FileFormat::Csv),
+            "vcard" | "vcf" => Ok(FileFormat::Vcard(Version::V4)),
+            "vcard3" => Ok(FileFormat::Vcard(Version::V3)),
+            "ldif" => Ok(FileFormat::Ldif),
+            "abook" => Ok(FileFormat::Abook),
+            "mutt" => Ok(FileFormat::Mutt),
+            #[cfg(feature = "json")]
+            "json" => Ok(FileFormat::Json),
+            _ => Err(format!("unknown file format {}", s)),
+        }
+    }
+}
+
+/// How lists of entries are printed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Output {
+    Table,
+    /// CSV with a header row, in the columns of the table.
+    Csv,
+    #[cfg(feature = "json")]
+    Json,
+}
+
+impl Output {
+    fn from(s: &str) -> Result<Output, String> {
+        match s {
+            "table" => Ok(Output::Table),
+            "csv" => Ok(Output::Csv),
+            #[cfg(feature = "json")]
+            "json" => Ok(Output::Json),
+            _ => Err(format!("unknown output format {}", s)),
+        }
+    }
+}
+
+/// What list orders entries by.
+#[derive(Clone, Copy)]
+enum SortKey {
+    Field(Field),
+    Created,
+    Updated,
+}
+
+impl SortKey {
+    fn from(s: &str) -> Result<SortKey, String> {
+        match s {
+            "created" => Ok(SortKey::Created),
+            "updated" => Ok(SortKey::Updated),
+            _ => match Field::from(s)? {
+                Field::Any => Err("any is not a sort key".to_string()),
+                f => Ok(SortKey::Field(f)),
+            },
+        }
+    }
+
+    fn compare(&self, a: &Entry, b: &Entry) -> Ordering {
+        match self {
+            SortKey::Field(Field::Id) => a.id.cmp(&b.id),
+            SortKey::Field(f) => a.get(*f).to_lowercase().cmp(&b.get(*f).to_lowercase()),
+            SortKey::Created => a.created_at.cmp(&b.created_at),
+            SortKey::Updated => a.updated_at.cmp(&b.updated_at),
+        }
+    }
+}
+
+/// Parses the comma separated keys of `--sort`, descending if prefixed
+/// with `-`.
+fn parse_sort(s: &str) -> Result<Vec<(SortKey, bool)>, String> {
+    s.split(',')
+        .map(|k| k.trim())
+        .filter(|k| !k.is_empty())
+        .map(|k| match k.strip_prefix('-') {
+            Some(key) => SortKey::from(key).map(|x| (x, true)),
+            None => SortKey::from(k).map(|x| (x, false)),
+        })
+        .collect()
+}
+
+fn sort_entries(entries: &mut [Entry], keys: &[(SortKey, bool)]) {
+    entries.sort_by(|a, b| {
+        keys.iter()
+            .map(|(key, descending)| match descending {
+                true => key.compare(b, a),
+                false => key.compare(a, b),
+            })
+            .find(|o| *o != Ordering::Equal)
+            .unwrap_or(a.id.cmp(&b.id))
+    });
+}
+
+// columns of printed tables unless others are configured
+const TABLE_COLUMNS: [Field; 6] = [
+    Field::Id,
+    Field::FirstName,
+    Field::LastName,
+    Field::Phone,
+    Field::Address,
+    Field::Email,
+];
+
+// columns written by export unless others are given
+const EXPORT_COLUMNS: [Field; 11] = [
+    Field::Id,
+    Field::FirstName,
+    Field::LastName,
+    Field::Phone,
+    Field::Address,
+    Field::Email,
+    Field::Tag,
+    Field::Organization,
+    Field::Note,
+    Field::Birthday,
+    Field::Title,
+];
+
 enum ArgString {
     DatabasePath,
     ID,
@@ -17,7 +213,28 @@ enum ArgString {
     Phone,
     Address,
     Email,
+    Tags,
     SearchTerm,
+    Region,
+    PhoneFormat,
+    Output,
+    MinConfidence,
+    OnDuplicate,
+    Format,
+    Map,
+    Delimiter,
+    Encoding,
+    Columns,
+    BaseDn,
+    Profile,
+    KeyFile,
+    NewKeyFile,
+    Name,
+    Required,
+    Unique,
+    Sort,
+    Limit,
+    Page,
 }
 
 struct Parameters {
@@ -29,7 +246,43 @@ struct Parameters {
     phone: String,
     address: String,
     e_mail: String,
+    tags: String,
     search_term: String,
+    case_sensitive: bool,
+    accent_sensitive: bool,
+    fuzzy: bool,
+    phonetic: bool,
+    show_score: bool,
+    force: bool,
+    /// Create the phonebook if its file does not exist.
+    create: bool,
+    /// Whether to mask entries, None for the default of the phonebook.
+    redact: Option<bool>,
+    region: String,
+    /// None for the configured format, or as typed.
+    phone_format: Option<PhoneFormat>,
+    output: Option<Output>,
+    min_confidence: u8,
+    on_duplicate: OnDuplicate,
+    file_format: FileFormat,
+    mapping: String,
+    delimiter: Option<char>,
+    encoding: Option<Encoding>,
+    columns: String,
+    base_dn: String,
+    profile: Option<Profile>,
+    key_file: String,
+    new_key_file: String,
+    /// Name, required and unique fields of a new phonebook.
+    name: String,
+    required: String,
+    unique: String,
+    /// Order of listed entries, with whether each key is descending.
+    sort: Vec<(SortKey, bool)>,
+    /// Entries per page of list, 0 for all.
+    limit: usize,
+    page: usize,
+    positional: Vec<String>,
 }
 
 impl Default for Parameters {
@@ -43,13 +296,50 @@ impl Default for Parameters {
             phone: "".to_string(),
             address: "".to_string(),
             e_mail: "".to_string(),
+            tags: "".to_string(),
             search_term: "".to_string(),
+            case_sensitive: false,
+            accent_sensitive: false,
+            fuzzy: false,
+            phonetic: false,
+            show_score: false,
+            force: false,
+            create: false,
+            redact: None,
+            region: "".to_string(),
+            phone_format: None,
+            output: None,
+            min_confidence: 50,
+            on_duplicate: OnDuplicate::Warn,
+            file_format: FileFormat::Csv,
+            mapping: "".to_string(),
+            delimiter: None,
+            encoding: None,
+            columns: "".to_string(),
+            base_dn: "".to_string(),
+            profile: None,
+            key_file: "".to_string(),
+            new_key_file: "".to_string(),
+            name: "".to_string(),
+            required: "".to_string(),
+            unique: "".to_string(),
+            sort: Vec::new(),
+            limit: 0,
+            page: 0,
+            positional: Vec::new(),
         }
     }
 }
 
 impl Parameters {
-    fn to_entry(self) -> Entry {
+    fn match_options(&self) -> MatchOptions {
+        MatchOptions {
+            ignore_case: !self.case_sensitive,
+            ignore_accents: !self.accent_sensitive,
+        }
+    }
+
+    fn into_entry(self) -> Entry {
         Entry {
             id: self.id,
             first_name: self.first_name,
@@ -57,8 +347,8 @@ impl Parameters {
             phone: self.phone,
             address: self.address,
             e_mail: self.e_mail,
-            created_at: 0,
-            updated_at: 0,
+            tags: Entry::parse_tags(&self.tags),
+            ..Default::default()
         }
     }
 }
@@ -78,6 +368,13 @@ fn parse_arguments(args: Vec<String>) -> Result<Parameters, String> {
             None => break,
         };
         match param_name.as_str() {
+            // words after snapshots and gdpr, e.g. restore, are their subcommand
+            _ if matches!(result.operation, Operation::Snapshots | Operation::Gdpr)
+                && !param_name.starts_with('-') =>
+            {
+                result.positional.push(param_name);
+                continue;
+            }
             "-d" | "db-path" => param_type = ArgString::DatabasePath,
             "-i" | "id" => param_type = ArgString::ID,
             "-f" | "first-name" => param_type = ArgString::FirstName,
@@ -85,7 +382,28 @@ fn parse_arguments(args: Vec<String>) -> Result<Parameters, String> {
             "-p" | "phone" => param_type = ArgString::Phone,
             "-a" | "address" => param_type = ArgString::Address,
             "-e" | "e-mail" => param_type = ArgString::Email,
+            "-g" | "tags" => param_type = ArgString::Tags,
             "-t" | "term" => param_type = ArgString::SearchTerm,
+            "-r" | "region" => param_type = ArgString::Region,
+            "--phone-format" => param_type = ArgString::PhoneFormat,
+            "--output" => param_type = ArgString::Output,
+            "--min-confidence" => param_type = ArgString::MinConfidence,
+            "--on-duplicate" => param_type = ArgString::OnDuplicate,
+            "--format" => param_type = ArgString::Format,
+            "--map" => param_type = ArgString::Map,
+            "--delimiter" => param_type = ArgString::Delimiter,
+            "--encoding" => param_type = ArgString::Encoding,
+            "--columns" => param_type = ArgString::Columns,
+            "--base-dn" => param_type = ArgString::BaseDn,
+            "--profile" => param_type = ArgString::Profile,
+            "--key-file" => param_type = ArgString::KeyFile,
+            "--new-key-file" => param_type = ArgString::NewKeyFile,
+            "--name" => param_type = ArgString::Name,
+            "--required" => param_type = ArgString::Required,
+            "--unique" => param_type = ArgString::Unique,
+            "--sort" => param_type = ArgString::Sort,
+            "--limit" => param_type = ArgString::Limit,
+            "--page" => param_type = ArgString::Page,
             "create" => {
                 result.operation = Operation::Create;
                 continue;
@@ -102,10 +420,114 @@ fn parse_arguments(args: Vec<String>) -> Result<Parameters, String> {
                 result.operation = Operation::Search;
                 continue;
             }
+            "lookup" => {
+                result.operation = Operation::Lookup;
+                continue;
+            }
+            "duplicates" => {
+                result.operation = Operation::Duplicates;
+                continue;
+            }
+            "merge" => {
+                result.operation = Operation::Merge;
+                continue;
+            }
+            "import" => {
+                result.operation = Operation::Import;
+                continue;
+            }
+            "export" => {
+                result.operation = Operation::Export;
+                continue;
+            }
+            "query" => {
+                result.operation = Operation::Query;
+                continue;
+            }
+            "backup" => {
+                result.operation = Operation::Backup;
+                continue;
+            }
+            "restore" => {
+                result.operation = Operation::Restore;
+                continue;
+            }
+            "snapshots" => {
+                result.operation = Operation::Snapshots;
+                continue;
+            }
+            "encrypt" => {
+                result.operation = Operation::Encrypt;
+                continue;
+            }
+            "change-passphrase" => {
+                result.operation = Operation::ChangePassphrase;
+                continue;
+            }
+            "decrypt" => {
+                result.operation = Operation::Decrypt;
+                continue;
+            }
+            "gdpr" => {
+                result.operation = Operation::Gdpr;
+                continue;
+            }
+            "init" => {
+                result.operation = Operation::Init;
+                continue;
+            }
+            "list" => {
+                result.operation = Operation::List;
+                continue;
+            }
+            "show" => {
+                result.operation = Operation::Show;
+                continue;
+            }
+            "--case-sensitive" => {
+                result.case_sensitive = true;
+                continue;
+            }
+            "--accent-sensitive" => {
+                result.accent_sensitive = true;
+                continue;
+            }
+            "--fuzzy" => {
+                result.fuzzy = true;
+                continue;
+            }
+            "--phonetic" => {
+                result.phonetic = true;
+                continue;
+            }
+            "--show-score" => {
+                result.show_score = true;
+                continue;
+            }
+            "--force" => {
+                result.force = true;
+                continue;
+            }
+            "--create" => {
+                result.create = true;
+                continue;
+            }
+            "--redact" => {
+                result.redact = Some(true);
+                continue;
+            }
+            "--no-redact" => {
+                result.redact = Some(false);
+                continue;
+            }
             "-h" | "help" => {
                 result.operation = Operation::Help;
                 continue;
             }
+            _ if takes_positional(&result.operation) && !param_name.starts_with('-') => {
+                result.positional.push(param_name);
+                continue;
+            }
             _ => return Err(format!("unknown parameter {}", param_name).to_string()),
         };
         param_value = match it.next() {
@@ -125,106 +547,971 @@ fn parse_arguments(args: Vec<String>) -> Result<Parameters, String> {
             ArgString::Phone => result.phone = param_value,
             ArgString::Address => result.address = param_value,
             ArgString::Email => result.e_mail = param_value,
+            ArgString::Tags => result.tags = param_value,
             ArgString::SearchTerm => result.search_term = param_value,
+            ArgString::Region => result.region = param_value,
+            ArgString::PhoneFormat => {
+                result.phone_format = Some(PhoneFormat::from(param_value.as_str())?)
+            }
+            ArgString::Output => result.output = Some(Output::from(param_value.as_str())?),
+            ArgString::OnDuplicate => {
+                result.on_duplicate = OnDuplicate::from(param_value.as_str())?
+            }
+            ArgString::Format => result.file_format = FileFormat::from(param_value.as_str())?,
+            ArgString::Map => result.mapping = param_value,
+            ArgString::Delimiter => {
+                result.delimiter = match param_value.as_str() {
+                    "tab" | "\\t" => Some('\t'),
+                    _ if param_value.chars().count() == 1 => param_value.chars().next(),
+                    _ => return Err("delimiter must be a single character or tab".to_string()),
+                };
+            }
+            ArgString::Encoding => result.encoding = Some(Encoding::from(param_value.as_str())?),
+            ArgString::Columns => result.columns = param_value,
+            ArgString::BaseDn => result.base_dn = param_value,
+            ArgString::Profile => result.profile = Some(Profile::from(param_value.as_str())?),
+            ArgString::KeyFile => result.key_file = param_value,
+            ArgString::NewKeyFile => result.new_key_file = param_value,
+            ArgString::Name => result.name = param_value,
+            ArgString::Required => result.required = param_value,
+            ArgString::Unique => result.unique = param_value,
+            ArgString::Sort => result.sort = parse_sort(param_value.as_str())?,
+            ArgString::Limit => {
+                result.limit = match param_value.parse::<usize>() {
+                    Ok(x) if x > 0 => x,
+                    _ => return Err("limit must be a positive number".to_string()),
+                };
+            }
+            ArgString::Page => {
+                result.page = match param_value.parse::<usize>() {
+                    Ok(x) if x > 0 => x,
+                    _ => return Err("page must be a positive number".to_string()),
+                };
+            }
+            ArgString::MinConfidence => {
+                result.min_confidence = match param_value.parse::<u8>() {
+                    Ok(x) if x <= 100 => x,
+                    _ => return Err("min-confidence must be a number from 0 to 100".to_string()),
+                };
+            }
         };
     }
+    // lookup takes the number as a positional argument
+    if matches!(result.operation, Operation::Lookup)
+        && result.search_term.is_empty()
+        && !result.positional.is_empty()
+    {
+        result.search_term = result.positional.remove(0);
+    }
+    // query takes the words of the term as positional arguments
+    if matches!(result.operation, Operation::Query) && result.search_term.is_empty() {
+        result.search_term = result.positional.join(" ");
+        result.positional.clear();
+    }
     Ok(result)
 }
 
+/// Looks for existing entries with the phone number or e-mail of a new entry
+/// and applies the duplicate policy. Returns whether the entry should be
+/// created.
+fn check_duplicate(db: &dyn DB, entry: &Entry, policy: OnDuplicate) -> Result<bool, String> {
+    if policy == OnDuplicate::Create {
+        return Ok(true);
+    }
+    let existing = db.find_existing(entry);
+    if existing.is_empty() {
+        return Ok(true);
+    }
+    let names: Vec<String> = existing
+        .iter()
+        .map(|e| format!("#{} {} {}", e.id, e.first_name, e.last_name))
+        .collect();
+    let message = format!(
+        "{} {} has the phone number or e-mail of {}",
+        entry.first_name,
+        entry.last_name,
+        names.join(", ")
+    );
+    match policy {
+        OnDuplicate::Warn => {
+            println!("Warning: {}", message);
+            Ok(true)
+        }
+        OnDuplicate::Skip => {
+            println!("Skipping: {}", message);
+            Ok(false)
+        }
+        OnDuplicate::Fail => Err(message),
+        OnDuplicate::Prompt => {
+            print!("{}. Create anyway? [y/N] ", message);
+            let _ = io::stdout().flush();
+            let mut answer = String::new();
+            if let Err(e) = io::stdin().read_line(&mut answer) {
+                return Err(format!("failed to read answer: {}", e));
+            }
+            Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+        }
+        OnDuplicate::Create => Ok(true),
+    }
+}
+
+/// Returns true for commands taking arguments without parameter name.
+fn takes_positional(operation: &Operation) -> bool {
+    matches!(
+        operation,
+        Operation::Lookup
+            | Operation::Merge
+            | Operation::Import
+            | Operation::Export
+            | Operation::Query
+            | Operation::Backup
+            | Operation::Restore
+            | Operation::Snapshots
+            | Operation::Decrypt
+            | Operation::Init
+    )
+}
+
+/// Formats what is stored about a person for a data subject request.
+#[cfg(feature = "json")]
+fn subject_json(subject: &Subject) -> Result<String, String> {
+    json::write_subject(subject)
+}
+
+#[cfg(not(feature = "json"))]
+fn subject_json(_subject: &Subject) -> Result<String, String> {
+    Err("gdpr export needs the json feature".to_string())
+}
+
+/// Returns the key to open the phonebook with: the key file given, the key
+/// from the environment or, if the file is encrypted, a passphrase typed in.
+fn current_key(p: &Parameters) -> Result<Option<Key>, String> {
+    if !p.key_file.is_empty() {
+        return Ok(Some(Key::File(p.key_file.clone())));
+    }
+    if let Some(key) = Key::from_env() {
+        return Ok(Some(key));
+    }
+    match fs::read_to_string(p.database_path.as_str()) {
+        Ok(content) if is_encrypted(content.as_str()) => {
+            Ok(Some(Key::Passphrase(read_passphrase("Passphrase: ")?)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Asks for a passphrase without echoing it.
+#[cfg(feature = "encryption")]
+fn read_passphrase(prompt: &str) -> Result<String, String> {
+    match rpassword::prompt_password(prompt) {
+        Ok(x) => Ok(x),
+        Err(e) => Err(format!("failed to read passphrase: {}", e)),
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+fn read_passphrase(_prompt: &str) -> Result<String, String> {
+    Err("encrypted phonebooks need the encryption feature".to_string())
+}
+
+/// Asks for a new passphrase twice.
+fn new_passphrase() -> Result<Key, String> {
+    let passphrase = read_passphrase("New passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err("passphrase must not be empty".to_string());
+    }
+    match read_passphrase("Repeat new passphrase: ")? == passphrase {
+        true => Ok(Key::Passphrase(passphrase)),
+        false => Err("passphrases do not match".to_string()),
+    }
+}
+
+/// Entries read from an import file with the line they start on, and the
+/// records that could not be read.
+type Imported = (Vec<(usize, Entry)>, Vec<RowError>);
+
+/// Reads the entries of an import file.
+fn read_import(p: &Parameters, path: &str) -> Result<Imported, String> {
+    let bytes = match fs::read(path) {
+        Ok(x) => x,
+        Err(e) => return Err(format!("failed to read {}: {}", path, e)),
+    };
+    let text = decode(&bytes, p.encoding)?;
+    if p.profile.is_some() && p.file_format != FileFormat::Csv {
+        return Err("profiles can only be used with CSV files".to_string());
+    }
+    match p.file_format {
+        FileFormat::Csv => {
+            let import = match p.profile {
+                Some(profile) => profile.read_entries(text.as_str(), p.delimiter)?,
+                None => {
+                    let options = CsvOptions {
+                        delimiter: p.delimiter,
+                        mapping: csv::parse_mapping(p.mapping.as_str())?,
+                    };
+                    csv::read_entries(text.as_str(), &options)?
+                }
+            };
+            if !import.ignored.is_empty() {
+                println!("Ignoring columns: {}", import.ignored.join(", "));
+            }
+            Ok((import.entries, import.errors))
+        }
+        FileFormat::Vcard(_) => Ok(vcard::read_entries(text.as_str())),
+        FileFormat::Ldif => Ok(ldif::read_entries(text.as_str())),
+        FileFormat::Abook => Ok(abook::read_entries(text.as_str())),
+        FileFormat::Mutt => Ok(mutt::read_entries(text.as_str())),
+        #[cfg(feature = "json")]
+        FileFormat::Json => Err("JSON files can only be exported".to_string()),
+    }
+}
+
+/// Creates imported entries one by one, validating each before applying the
+/// duplicate policy, and saves them at once. Entries that can not be created
+/// are added to the errors. Returns the number of created entries.
+fn import_entries(
+    db: &mut dyn DB,
+    entries: Vec<(usize, Entry)>,
+    errors: &mut Vec<RowError>,
+    policy: OnDuplicate,
+) -> Result<usize, String> {
+    let mut created = 0;
+    for (line, entry) in entries {
+        if let Err(message) = db.check(&entry) {
+            errors.push(RowError { line, message });
+            continue;
+        }
+        match check_duplicate(db, &entry, policy) {
+            Ok(true) => (),
+            Ok(false) => continue,
+            Err(message) => {
+                errors.push(RowError { line, message });
+                continue;
+            }
+        }
+        match db.insert(entry) {
+            Ok(_) => created += 1,
+            Err(message) => errors.push(RowError { line, message }),
+        }
+    }
+    errors.sort_by_key(|e| e.line);
+    if created > 0 {
+        db.flush()?;
+    }
+    Ok(created)
+}
+
+/// Parses the comma separated fields of `--columns`.
+fn parse_columns(s: &str) -> Result<Vec<Field>, String> {
+    let columns = s
+        .split(',')
+        .map(|c| Field::from(c.trim()))
+        .collect::<Result<Vec<Field>, String>>()?;
+    if columns.contains(&Field::Any) {
+        return Err("any is not a column".to_string());
+    }
+    Ok(columns)
+}
+
+/// Header of a phonebook created by init.
+fn init_metadata(p: &Parameters) -> Metadata {
+    let mut meta = Metadata::default();
+    let values = [
+        ("name", &p.name),
+        ("region", &p.region),
+        ("required", &p.required),
+        ("unique", &p.unique),
+    ];
+    for (key, value) in values.iter().filter(|(_, v)| !v.is_empty()) {
+        meta.set(key, value);
+    }
+    meta
+}
+
+/// Formats entries for export.
+fn export_entries(p: &Parameters, entries: &[Entry]) -> Result<String, String> {
+    let columns: Vec<Field> = match p.columns.is_empty() {
+        true => EXPORT_COLUMNS.to_vec(),
+        false => parse_columns(p.columns.as_str())?,
+    };
+    match (p.file_format, p.profile) {
+        (FileFormat::Csv, Some(profile)) => Ok(profile.write_entries(entries)),
+        (_, Some(_)) => Err("profiles can only be used with CSV files".to_string()),
+        (FileFormat::Csv, None) => Ok(csv::write_entries(
+            entries,
+            &columns,
+            p.delimiter.unwrap_or(','),
+        )),
+        (FileFormat::Vcard(version), None) => Ok(vcard::write_entries(entries, version)),
+        (FileFormat::Ldif, None) => Ok(ldif::write_entries(entries, p.base_dn.as_str())),
+        (FileFormat::Abook, None) => Ok(abook::write_entries(entries)),
+        (FileFormat::Mutt, None) => Ok(mutt::write_entries(entries)),
+        #[cfg(feature = "json")]
+        (FileFormat::Json, None) => json::write_entries(entries),
+    }
+}
+
 pub fn execute(args: Vec<String>) {
     let parameters = parse_arguments(args);
     match parameters {
-        Ok(p) => {
+        Ok(mut p) => {
             match p.operation {
-                Operation::Help => print_help_msg(),
+                Operation::Help => return print_help_msg(),
                 Operation::None => {
                     println!("Error: Unsupported command");
                     std::process::exit(1);
                 }
+                _ => (),
+            }
+            let config = match Config::load() {
+                Ok(x) => x,
+                Err(msg) => {
+                    println!("Error: {}", msg);
+                    std::process::exit(1);
+                }
+            };
+            // -d comes first, then PHONEBOOK_DB and then the configuration
+            if p.database_path.is_empty() {
+                p.database_path = match (env::var("PHONEBOOK_DB"), &config.database) {
+                    (Ok(path), _) if !path.is_empty() => path,
+                    (_, Some(path)) => path.clone(),
+                    _ => "".to_string(),
+                };
+            }
+            match p.operation {
+                Operation::Init => {
+                    if let Err(e) = check_init_params(&p) {
+                        println!("Error: {}", e);
+                        print_help_init();
+                        std::process::exit(1);
+                    }
+                    let path = p.positional.first().unwrap_or(&p.database_path).clone();
+                    match FileDB::init(path.clone(), &init_metadata(&p)) {
+                        Ok(_) => println!("Created phonebook {}", path),
+                        Err(msg) => {
+                            println!("Error: {}", msg);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                // the phonebook to restore may not exist yet
+                Operation::Restore => {
+                    if let Err(e) = check_restore_params(&p) {
+                        println!("Error: {}", e);
+                        print_help_backup();
+                        std::process::exit(1);
+                    }
+                    let archive = match fs::read_to_string(&p.positional[0]) {
+                        Ok(x) => x,
+                        Err(e) => {
+                            println!("Error: failed to read {}: {}", p.positional[0], e);
+                            std::process::exit(1);
+                        }
+                    };
+                    if !p.force && FileDB::has_content(p.database_path.as_str()) {
+                        println!(
+                            "Error: {} is not empty, use --force to overwrite it",
+                            p.database_path
+                        );
+                        std::process::exit(1);
+                    }
+                    let key = match current_key(&p) {
+                        Ok(None) if is_encrypted(archive.as_str()) => {
+                            read_passphrase("Passphrase: ").map(|x| Some(Key::Passphrase(x)))
+                        }
+                        x => x,
+                    };
+                    let key = match key {
+                        Ok(x) => x,
+                        Err(msg) => {
+                            println!("Error: {}", msg);
+                            std::process::exit(1);
+                        }
+                    };
+                    let was_encrypted = fs::read_to_string(p.database_path.as_str())
+                        .is_ok_and(|c| is_encrypted(c.as_str()));
+                    match FileDB::restore(p.database_path.clone(), archive.as_str(), p.force, key) {
+                        Ok(db) => {
+                            println!(
+                                "Restored {} entries to {}",
+                                db.read_all().len(),
+                                p.database_path
+                            );
+                            if was_encrypted && !db.is_encrypted() {
+                                println!(
+                                    "Warning: {} was encrypted, the backup is not and was restored in plain text, use encrypt",
+                                    p.database_path
+                                );
+                            }
+                        }
+                        Err(msg) => {
+                            println!("Error: {}", msg);
+                            std::process::exit(1);
+                        }
+                    }
+                }
                 _ => {
-                    match FileDB::new(p.database_path.clone()) {
-                        Ok(mut db) => match p.operation {
-                            Operation::Create => {
-                                match check_create_params(&p) {
+                    if p.database_path.is_empty() {
+                        println!("Error: database path must be stated with -d, PHONEBOOK_DB or the database setting");
+                        std::process::exit(1);
+                    }
+                    if p.create && fs::metadata(p.database_path.as_str()).is_err() {
+                        if let Err(msg) =
+                            FileDB::init(p.database_path.clone(), &Metadata::default())
+                        {
+                            println!("Error: {}", msg);
+                            std::process::exit(1);
+                        }
+                    }
+                    let key = match current_key(&p) {
+                        Ok(x) => x,
+                        Err(msg) => {
+                            println!("Error: {}", msg);
+                            std::process::exit(1);
+                        }
+                    };
+                    let mut db = match FileDB::open(p.database_path.clone(), key) {
+                        Ok(db) => db,
+                        Err(msg) => {
+                            println!("Error: {}", msg);
+                            std::process::exit(1);
+                        }
+                    };
+                    // the configured region only applies to phonebooks without one
+                    let region = match (p.region.is_empty(), &config.region) {
+                        (false, _) => Some(p.region.clone()),
+                        (true, Some(code)) if !db.has_region() => Some(code.clone()),
+                        _ => None,
+                    };
+                    if let Some(code) = region {
+                        if let Err(msg) = db.set_region(code.as_str()) {
+                            println!("Error: {}", msg);
+                            std::process::exit(1);
+                        }
+                    }
+                    let columns = match (p.columns.is_empty(), &config.columns) {
+                        (false, _) => parse_columns(p.columns.as_str()),
+                        (true, Some(columns)) => Ok(columns.clone()),
+                        (true, None) => Ok(TABLE_COLUMNS.to_vec()),
+                    };
+                    let format = Presentation {
+                        phone_format: p
+                            .phone_format
+                            .or(config.phone_format)
+                            .unwrap_or(PhoneFormat::Original),
+                        output: p.output.or(config.output).unwrap_or(Output::Table),
+                        columns: match columns {
+                            Ok(x) => x,
+                            Err(msg) => {
+                                println!("Error: {}", msg);
+                                std::process::exit(1);
+                            }
+                        },
+                        redaction: match p
+                            .redact
+                            .or(config.redact)
+                            .unwrap_or(db.redaction().by_default)
+                        {
+                            true => Some(db.redaction().clone()),
+                            false => None,
+                        },
+                    };
+                    match p.operation {
+                        Operation::Create => {
+                            let on_duplicate = p.on_duplicate;
+                            let entry = p.into_entry();
+                            if let Err(msg) = db.check(&entry) {
+                                println!("Error: {}", msg);
+                                print_help_create();
+                                std::process::exit(1);
+                            }
+                            match check_duplicate(&db, &entry, on_duplicate) {
+                                Ok(true) => (),
+                                Ok(false) => {
+                                    println!("Entry was not created");
+                                    return;
+                                }
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            }
+                            match db.create(entry) {
+                                Ok(entry) => {
+                                    println!("Successfully created entry");
+                                    print_single_entry(&present(entry, &format));
+                                }
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    print_help_create();
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                        Operation::Update => {
+                            if let Err(e) = check_update_params(&p) {
+                                println!("Error: {}", e);
+                                print_help_delete();
+                                std::process::exit(1);
+                            }
+                            match db.update(p.id, p.into_entry()) {
+                                Ok(entry) => {
+                                    println!("Successfully updated entry");
+                                    print_single_entry(&present(entry, &format));
+                                }
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                        Operation::Delete => {
+                            if let Err(e) = check_delete_params(&p) {
+                                println!("Error: {}", e);
+                                print_help_delete();
+                                std::process::exit(1);
+                            }
+                            match db.delete(p.id) {
+                                Ok(entry) => {
+                                    println!("Successfully deleted entry");
+                                    print_single_entry(&present(entry, &format));
+                                }
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            };
+                        }
+                        Operation::List => {
+                            if let Err(e) = check_list_params(&p) {
+                                println!("Error: {}", e);
+                                print_help_list();
+                                std::process::exit(1);
+                            }
+                            let mut entries = match p.search_term.is_empty() {
+                                true => db.read_all(),
+                                false => match Query::parse(p.search_term.as_str()) {
+                                    Ok(q) => db.search(&q.with_options(p.match_options())),
                                     Err(e) => {
-                                        println!("Error: {}", e);
-                                        print_help_create();
+                                        println!("Error: invalid filter: {}", e);
+                                        print_help_list();
                                         std::process::exit(1);
                                     }
-                                    _ => (),
-                                };
-                                match db.create(p.to_entry()) {
-                                    Ok(entry) => {
-                                        println!("Successfully created entry");
-                                        print_single_entry(&entry);
-                                    }
+                                },
+                            };
+                            sort_entries(&mut entries, &p.sort);
+                            let total = entries.len();
+                            if p.limit > 0 {
+                                let page = p.page.max(1);
+                                let (entries, pages) = match page_of(entries, p.limit, page) {
+                                    Ok(x) => x,
                                     Err(msg) => {
                                         println!("Error: {}", msg);
                                         std::process::exit(1);
                                     }
+                                };
+                                print_all_entries(present_all(entries, &format), &format);
+                                if format.output == Output::Table {
+                                    println!("Page {} of {}, {} entries", page, pages, total);
                                 }
+                            } else {
+                                print_all_entries(present_all(entries, &format), &format);
                             }
-                            Operation::Update => {
-                                match check_create_params(&p) {
-                                    Err(e) => {
-                                        println!("Error: {}", e);
-                                        print_help_delete();
+                        }
+                        Operation::Show => {
+                            if let Err(e) = check_param_id(p.id) {
+                                println!("Error: {}", e);
+                                print_help_list();
+                                std::process::exit(1);
+                            }
+                            match db.read_by_id(p.id) {
+                                Some(entry) => print_entry_detail(&present(entry, &format)),
+                                None => {
+                                    println!("Error: entry with ID {} does not exist", p.id);
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                        Operation::Search => {
+                            if let Err(e) = check_search_params(&p) {
+                                println!("Error: {}", e);
+                                print_help_search();
+                                std::process::exit(1);
+                            }
+                            if p.phonetic {
+                                print_all_entries(
+                                    present_all(
+                                        db.phonetic_search(p.search_term.as_str()),
+                                        &format,
+                                    ),
+                                    &format,
+                                );
+                                return;
+                            }
+                            if p.fuzzy {
+                                let matches = db
+                                    .fuzzy_search(p.search_term.as_str(), p.match_options())
+                                    .into_iter()
+                                    .map(|m| Match {
+                                        entry: present(m.entry, &format),
+                                        score: m.score,
+                                    })
+                                    .collect();
+                                print_ranked_entries(matches, p.show_score, &format);
+                                return;
+                            }
+                            let query = match Query::parse(p.search_term.as_str()) {
+                                Ok(q) => q.with_options(p.match_options()),
+                                Err(e) => {
+                                    println!("Error: invalid search term: {}", e);
+                                    print_help_search();
+                                    std::process::exit(1);
+                                }
+                            };
+                            print_all_entries(present_all(db.search(&query), &format), &format);
+                        }
+                        Operation::Lookup => {
+                            if let Err(e) = check_lookup_params(&p) {
+                                println!("Error: {}", e);
+                                print_help_lookup();
+                                std::process::exit(1);
+                            }
+                            let entries = db.lookup(p.search_term.as_str());
+                            if entries.is_empty() {
+                                println!("No entries found for {}", p.search_term);
+                                std::process::exit(1);
+                            }
+                            print_all_entries(present_all(entries, &format), &format);
+                        }
+                        Operation::Duplicates => {
+                            print_duplicates(db.duplicates(p.min_confidence));
+                        }
+                        Operation::Merge => {
+                            let (keep, other) = match check_merge_params(&p) {
+                                Ok(x) => x,
+                                Err(e) => {
+                                    println!("Error: {}", e);
+                                    print_help_merge();
+                                    std::process::exit(1);
+                                }
+                            };
+                            match db.merge(keep, other) {
+                                Ok(entry) => {
+                                    println!(
+                                        "Successfully merged entry {} into entry {}",
+                                        other, keep
+                                    );
+                                    print_single_entry(&present(entry, &format));
+                                }
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                        Operation::Query => {
+                            if let Err(e) = check_search_params(&p) {
+                                println!("Error: {}", e);
+                                print_help_query();
+                                std::process::exit(1);
+                            }
+                            let query = match Query::parse(p.search_term.as_str()) {
+                                Ok(q) => q.with_options(p.match_options()),
+                                Err(e) => {
+                                    println!("Error: invalid search term: {}", e);
+                                    std::process::exit(1);
+                                }
+                            };
+                            // only entries with an e-mail address can be recipients
+                            let entries: Vec<Entry> = db
+                                .search(&query)
+                                .into_iter()
+                                .filter(|e| !e.e_mail.trim().is_empty())
+                                .collect();
+                            if entries.is_empty() {
+                                println!("No entries found for {}", p.search_term);
+                                std::process::exit(1);
+                            }
+                            print_query_results(present_all(entries, &format));
+                        }
+                        Operation::Import => {
+                            if let Err(e) = check_import_params(&p) {
+                                println!("Error: {}", e);
+                                print_help_import();
+                                std::process::exit(1);
+                            }
+                            let (entries, mut errors) =
+                                match read_import(&p, p.positional[0].as_str()) {
+                                    Ok(x) => x,
+                                    Err(msg) => {
+                                        println!("Error: {}", msg);
                                         std::process::exit(1);
                                     }
-                                    _ => (),
                                 };
-                                match db.update(p.id, p.to_entry()) {
-                                    Ok(entry) => {
-                                        println!("Successfully updated entry");
-                                        print_single_entry(&entry);
-                                    }
+                            let total = entries.len();
+                            let created =
+                                match import_entries(&mut db, entries, &mut errors, p.on_duplicate)
+                                {
+                                    Ok(x) => x,
                                     Err(msg) => {
                                         println!("Error: {}", msg);
                                         std::process::exit(1);
                                     }
+                                };
+                            println!("Imported {} of {} entries", created, total);
+                            if !errors.is_empty() {
+                                println!("Failed records:");
+                                for e in errors.iter() {
+                                    println!("  {}", e);
                                 }
+                                std::process::exit(1);
                             }
-                            Operation::Delete => {
-                                match check_delete_params(&p) {
-                                    Err(e) => {
-                                        println!("Error: {}", e);
-                                        print_help_delete();
-                                        std::process::exit(1);
-                                    }
-                                    _ => (),
-                                };
-                                match db.delete(p.id) {
-                                    Ok(entry) => {
-                                        println!("Successfully deleted entry");
-                                        print_single_entry(&entry);
+                        }
+                        Operation::Export => {
+                            if p.positional.len() > 1 {
+                                println!("Error: only one file can be exported to");
+                                print_help_import();
+                                std::process::exit(1);
+                            }
+                            let entries = present_all(db.read_all(), &format);
+                            let content = match export_entries(&p, &entries) {
+                                Ok(x) => x,
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            };
+                            match p.positional.first() {
+                                Some(path) => match fs::write(path, content) {
+                                    Ok(()) => {
+                                        println!("Exported {} entries to {}", entries.len(), path)
                                     }
-                                    Err(msg) => {
-                                        println!("Error: {}", msg);
+                                    Err(e) => {
+                                        println!("Error: failed to write {}: {}", path, e);
                                         std::process::exit(1);
                                     }
-                                };
+                                },
+                                None => print!("{}", content),
+                            }
+                        }
+                        Operation::Backup => {
+                            if p.positional.len() > 1 {
+                                println!("Error: only one file can be backed up to");
+                                print_help_backup();
+                                std::process::exit(1);
                             }
-                            Operation::Search => {
-                                match check_search_params(&p) {
+                            let archive = match db.backup() {
+                                Ok(x) => x,
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            };
+                            match p.positional.first() {
+                                Some(path) => match fs::write(path, archive) {
+                                    Ok(()) => println!(
+                                        "Backed up {} entries to {}",
+                                        db.read_all().len(),
+                                        path
+                                    ),
                                     Err(e) => {
-                                        println!("Error: {}", e);
-                                        print_help_search();
+                                        println!("Error: failed to write {}: {}", path, e);
                                         std::process::exit(1);
                                     }
-                                    _ => (),
-                                };
-                                print_all_entries(db.search(p.search_term));
+                                },
+                                None => print!("{}", archive),
                             }
+                        }
+                        Operation::Snapshots => match p
+                            .positional
+                            .iter()
+                            .map(|s| s.as_str())
+                            .collect::<Vec<&str>>()[..]
+                        {
+                            ["list"] | [] => match db.snapshots() {
+                                Ok(snapshots) if snapshots.is_empty() => {
+                                    println!("No snapshots found")
+                                }
+                                Ok(snapshots) => {
+                                    for s in snapshots.iter() {
+                                        println!(
+                                            "{}  {}",
+                                            s.name,
+                                            DateTime::from_timestamp(s.created_at).to_extended()
+                                        );
+                                    }
+                                }
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            },
+                            ["restore", name] => match db.restore_snapshot(name) {
+                                Ok(()) => println!(
+                                    "Restored snapshot {} with {} entries",
+                                    name,
+                                    db.read_all().len()
+                                ),
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            },
                             _ => {
-                                println!("Error: Thist code should be unreachable");
+                                println!(
+                                    "Error: unknown snapshots command {}",
+                                    p.positional.join(" ")
+                                );
+                                print_help_snapshots();
                                 std::process::exit(1);
                             }
                         },
-                        Err(msg) => {
-                            println!("Error: {}", msg);
+                        Operation::Encrypt => {
+                            if db.is_encrypted() {
+                                println!(
+                                    "Error: phonebook is encrypted already, use change-passphrase"
+                                );
+                                print_help_encryption();
+                                std::process::exit(1);
+                            }
+                            let key = match Key::from_env() {
+                                _ if !p.key_file.is_empty() => Ok(Key::File(p.key_file.clone())),
+                                Some(key) => Ok(key),
+                                None => new_passphrase(),
+                            };
+                            match key.and_then(|k| db.set_key(Some(k))) {
+                                Ok(unreadable) => {
+                                    println!("Encrypted {}", p.database_path);
+                                    warn_unreadable_snapshots(&p.database_path, &unreadable);
+                                }
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                        Operation::ChangePassphrase => {
+                            if !db.is_encrypted() {
+                                println!("Error: phonebook is not encrypted, use encrypt");
+                                print_help_encryption();
+                                std::process::exit(1);
+                            }
+                            let key = match std::env::var("PHONEBOOK_NEW_PASSPHRASE") {
+                                _ if !p.new_key_file.is_empty() => {
+                                    Ok(Key::File(p.new_key_file.clone()))
+                                }
+                                Ok(x) if !x.is_empty() => Ok(Key::Passphrase(x)),
+                                _ => new_passphrase(),
+                            };
+                            match key.and_then(|k| db.set_key(Some(k))) {
+                                Ok(unreadable) => {
+                                    println!("Changed the key of {}", p.database_path);
+                                    warn_unreadable_snapshots(&p.database_path, &unreadable);
+                                }
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                        Operation::Decrypt => {
+                            if p.positional.len() > 1 {
+                                println!("Error: only one file can be decrypted to");
+                                print_help_encryption();
+                                std::process::exit(1);
+                            }
+                            if !db.is_encrypted() {
+                                println!("Error: phonebook is not encrypted");
+                                std::process::exit(1);
+                            }
+                            let result = match p.positional.first() {
+                                Some(path) => {
+                                    db.dump()
+                                        .and_then(|content| match fs::write(path, content) {
+                                            Ok(()) => Ok(format!(
+                                                "Decrypted phonebook written to {}",
+                                                path
+                                            )),
+                                            Err(e) => {
+                                                Err(format!("failed to write {}: {}", path, e))
+                                            }
+                                        })
+                                }
+                                None => db.set_key(None).map(|unreadable| {
+                                    warn_unreadable_snapshots(&p.database_path, &unreadable);
+                                    format!("Decrypted {}", p.database_path)
+                                }),
+                            };
+                            match result {
+                                Ok(msg) => println!("{}", msg),
+                                Err(msg) => {
+                                    println!("Error: {}", msg);
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                        Operation::Gdpr => {
+                            if let Err(e) = check_param_id(p.id) {
+                                println!("Error: {}", e);
+                                print_help_gdpr();
+                                std::process::exit(1);
+                            }
+                            match p
+                                .positional
+                                .iter()
+                                .map(|s| s.as_str())
+                                .collect::<Vec<&str>>()[..]
+                            {
+                                ["export"] | ["export", _] => {
+                                    let content = db.subject(p.id).and_then(|s| subject_json(&s));
+                                    let content = match content {
+                                        Ok(x) => x,
+                                        Err(msg) => {
+                                            println!("Error: {}", msg);
+                                            std::process::exit(1);
+                                        }
+                                    };
+                                    match p.positional.get(1) {
+                                        Some(path) => match fs::write(path, content) {
+                                            Ok(()) => println!(
+                                                "Exported data of entry {} to {}",
+                                                p.id, path
+                                            ),
+                                            Err(e) => {
+                                                println!("Error: failed to write {}: {}", path, e);
+                                                std::process::exit(1);
+                                            }
+                                        },
+                                        None => print!("{}", content),
+                                    }
+                                }
+                                ["erase"] => match db.erase(p.id) {
+                                    Ok(snapshots) => {
+                                        println!(
+                                            "Erased entry {} and its copies in {} snapshots",
+                                            p.id, snapshots
+                                        )
+                                    }
+                                    Err(msg) => {
+                                        println!("Error: {}", msg);
+                                        std::process::exit(1);
+                                    }
+                                },
+                                _ => {
+                                    println!(
+                                        "Error: unknown gdpr command {}",
+                                        p.positional.join(" ")
+                                    );
+                                    print_help_gdpr();
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                        _ => {
+                            println!("Error: Thist code should be unreachable");
                             std::process::exit(1);
                         }
-                    };
+                    }
                 }
             }
         }
@@ -242,7 +1529,23 @@ fn print_help_msg() {
     println!("     create - Create new entry");
     println!("     update - Update existing entry");
     println!("     delete - Delete entry");
-    println!("     search - Search for entries containing term");
+    println!("     search - Search for entries matching term");
+    println!("     lookup - Find entries by phone number");
+    println!("       list - List all entries, sorted, filtered and in pages");
+    println!("       show - Print all fields of an entry");
+    println!(" duplicates - List entries which are likely duplicates");
+    println!("      merge - Merge two entries into one");
+    println!("     import - Import entries from a file");
+    println!("     export - Export all entries to a file or standard output");
+    println!("      query - Search for e-mail recipients, printed for mail clients such as mutt");
+    println!("     backup - Write a backup of the whole phonebook");
+    println!("    restore - Recreate a phonebook from a backup");
+    println!("  snapshots - List or restore snapshots taken before changes");
+    println!("    encrypt - Encrypt the phonebook file with a passphrase or key file");
+    println!("  change-passphrase - Encrypt the phonebook file with a new passphrase or key file");
+    println!("    decrypt - Store the phonebook in plain text, or write a plain copy");
+    println!("       gdpr - Export or erase all data about a person");
+    println!("       init - Create a new phonebook");
     println!("  help | -h - Print this message");
     println!("Parameters:");
     println!("  -i | id         - ID number of entry");
@@ -251,14 +1554,100 @@ fn print_help_msg() {
     println!("  -p | phone      - Phone number");
     println!("  -a | address    - Address");
     println!("  -e | e-mail     - E-mail address");
+    println!("  -g | tags       - Comma separated list of tags");
     println!("  -t | term       - Search term");
-    println!("  -d | db-path    - File path of the database");
+    println!("  -d | db-path    - File path of the database, stored as JSON Lines if it ends with .jsonl");
+    println!("  -r | region     - Region (e.g. US, GB, RS) of phone numbers written without country code");
+    println!("  --phone-format  - Print phone numbers as typed (original), in national, international or e164 format");
+    println!("  --min-confidence - Lowest confidence in percent of listed duplicates (default 50)");
+    println!("  --on-duplicate  - When the phone or e-mail of a new entry exists: warn (default), skip, fail,");
+    println!("                    create or prompt");
+    println!("  --format        - Format of imported and exported files: csv (default), vcard (4.0, reads 3.0)");
+    println!("                    vcard3 (writes 3.0), ldif, abook, mutt or json (export only)");
+    println!(
+        "  --map           - Fields of import columns, e.g. \"Given Name=first,Mobile=phone\""
+    );
+    println!(
+        "  --delimiter     - Field delimiter of CSV files, detected on import (export default ,)"
+    );
+    println!(
+        "  --encoding      - Encoding of imported files: utf-8, utf-16le, utf-16be or windows-1252"
+    );
+    println!(
+        "  --columns       - Comma separated fields to export (default all) or to print in tables"
+    );
+    println!("  --output        - Print lists of entries as a table (default), csv or json");
+    println!("  --profile       - CSV layout of another address book: google or outlook");
+    println!("  --base-dn       - DN below which LDIF records are exported, e.g. ou=people,dc=example,dc=com");
+    println!("  --key-file      - Key file of an encrypted phonebook");
+    println!("  --new-key-file  - Key file to encrypt the phonebook with by change-passphrase");
+    println!("  --name          - Name of a phonebook created by init");
+    println!("  --required      - Comma separated fields every entry of a new phonebook must have");
+    println!(
+        "  --unique        - Comma separated fields no two entries of a new phonebook may share"
+    );
+    println!("  --sort          - Comma separated fields to list entries by, - before a field reverses it");
+    println!("  --limit         - Number of listed entries per page");
+    println!("  --page          - Page of listed entries to print (default 1)");
+    println!("Flags:");
+    println!("  --case-sensitive   - Search distinguishes upper and lower case");
+    println!("  --accent-sensitive - Search distinguishes accented letters (e.g. e and é)");
+    println!("  --fuzzy            - Search tolerates typos and orders results by relevance");
+    println!("  --phonetic         - Search for names that sound like the term");
+    println!("  --show-score       - Print relevance score of fuzzy search results, needs --fuzzy");
+    println!("  --force            - Restore over a phonebook that is not empty");
+    println!("  --create           - Create the phonebook if it does not exist, as init would");
+    println!("  --redact           - Mask fields when printing and exporting: those of the #redact=<fields>");
+    println!(
+        "                       header line, of #redact.<tag>=<fields> for tagged entries, or else"
+    );
+    println!("                       phone, address and e-mail");
+    println!(
+        "  --no-redact        - Show all fields although the header has #redact_by_default=true"
+    );
+    print_help_config();
+}
+
+fn print_help_config() {
+    println!("Configuration:");
+    println!("  Settings are read from /etc/phonebook/config.toml, then $XDG_CONFIG_HOME/phonebook/config.toml");
+    println!("  (~/.config/phonebook/config.toml) and then the file named by PHONEBOOK_CONFIG, later files");
+    println!("  overriding earlier ones:");
+    println!("     database = \"~/phonebook.txt\"");
+    println!("     region = \"GB\"");
+    println!("     phone_format = \"international\"");
+    println!("     output = \"table\"");
+    println!("     columns = [\"id\", \"name\", \"phone\", \"email\"]");
+    println!("     redact = false");
+    println!(
+        "  Parameters override the environment, which overrides the configuration: the database is"
+    );
+    println!(
+        "  -d, else PHONEBOOK_DB, else the database setting. The configured region only applies to"
+    );
+    println!("  phonebooks without a #region header line, while -r overrides both.");
 }
 
 fn print_help_create() {
     println!("Usage of create command");
-    println!("     create -f John -l Smith -p 123 [-a \"My street 12a\"] [-e johnsmith@gmail.com]");
-    println!("     create first-name John last-name Smith phone 123 [address \"My street 12a\"] [e-mail johnsmith@gmail.com]");
+    println!("     create -f John -l Smith -p \"+1 555 123 4567\" [-a \"My street 12a\"] [-e johnsmith@gmail.com] [-g work,friends]");
+    println!("     create first-name John last-name Smith phone \"+1 555 123 4567\" [address \"My street 12a\"] [e-mail johnsmith@gmail.com] [tags work,friends]");
+    println!("Phone numbers are stored together with their canonical international form. Numbers");
+    println!("without country code (+ or 00) need a region to determine it:");
+    println!("     create -f John -l Smith -p \"(555) 123-4567\" -r US");
+    println!("If an entry with the same phone number or e-mail exists, a warning is printed. Use");
+    println!("--on-duplicate skip|fail|create|prompt to skip the entry, fail, create it silently or ask.");
+    println!(
+        "Entries are checked against the validation rules of the phonebook, set in the header"
+    );
+    println!("of the database file (#required=first,last,phone, #max-length=address:100,");
+    println!(
+        "#check-email=true, #check-phone=true, #forbidden-chars=<>). All violations are reported."
+    );
+    println!(
+        "With #unique=phone,email no two entries may share a phone number or e-mail; creating,"
+    );
+    println!("updating or importing an entry that would is refused, naming the existing entry.");
 }
 
 fn print_help_delete() {
@@ -267,10 +1656,271 @@ fn print_help_delete() {
     println!("     delete id 123");
 }
 
+fn print_help_list() {
+    println!("Usage of list and show commands");
+    println!("     list [-t 'tag:work'] [--sort last,first] [--limit 20 [--page 2]]");
+    println!("     show -i 123");
+    println!("List prints all entries, or those matching a search term (see search), ordered by ID unless");
+    println!("--sort gives comma separated fields, created or updated; prefix a key with - to reverse it:");
+    println!("     list --sort -updated");
+    println!("With --limit entries are printed in pages of that size. Show prints every field of an entry,");
+    println!("including custom fields and when it was created and last updated.");
+}
+
 fn print_help_search() {
     println!("Usage of search command");
     println!("     search -t \"John Smith\"");
     println!("     search term \"John Smith\"");
+    println!(
+        "Terms are joined with AND unless separated by OR. Use field:value to search a single"
+    );
+    println!(
+        "field (id, name, first, last, phone, address, email, tag), -term or NOT term to exclude,"
+    );
+    println!(
+        "\"...\" for phrases and parentheses for grouping. Words with a colon that is not after"
+    );
+    println!("a field name, e.g. 12:30, are searched as they are:");
+    println!("     search -t 'last:smith phone:555 -tag:old'");
+    println!("     search -t '(first:John OR first:Jane) \"main street\"'");
+    println!(
+        "Matching ignores case and accents unless --case-sensitive or --accent-sensitive is given."
+    );
+    println!("With --fuzzy the term is plain text, misspelled words are matched and the best");
+    println!("matches are listed first:");
+    println!("     search -t \"jonh smiht\" --fuzzy [--show-score]");
+    println!(
+        "With --phonetic every word of the term is compared by sound with first and last names:"
+    );
+    println!("     search -t \"Jon Smyth\" --phonetic");
+}
+
+fn print_help_lookup() {
+    println!("Usage of lookup command");
+    println!("     lookup \"+1 555 123 4567\"");
+    println!("     lookup -t 5551234567");
+    println!(
+        "Formatting, international prefixes (+ or 00) and trunk zeros are ignored. Numbers stored"
+    );
+    println!("without area code match by suffix. Exits with status 1 if no entry is found.");
+}
+
+/// How entries are shown and exported.
+struct Presentation {
+    phone_format: PhoneFormat,
+    output: Output,
+    /// Columns of printed tables and CSV output.
+    columns: Vec<Field>,
+    /// Policy to mask entries with, None to show them as stored.
+    redaction: Option<Redaction>,
+}
+
+/// Prepares an entry for display.
+fn present(mut entry: Entry, format: &Presentation) -> Entry {
+    entry.phone = entry.formatted_phone(format.phone_format);
+    match &format.redaction {
+        Some(r) => r.apply(&entry),
+        None => entry,
+    }
+}
+
+fn present_all(entries: Vec<Entry>, format: &Presentation) -> Vec<Entry> {
+    entries.into_iter().map(|e| present(e, format)).collect()
+}
+
+fn print_help_import() {
+    println!("Usage of import and export commands");
+    println!("     import -d phonebook.txt contacts.csv [--map \"Given Name=first,Mobile=phone\"] [--on-duplicate skip]");
+    println!(
+        "     export -d phonebook.txt [contacts.csv] [--columns first,last,phone] [--delimiter ;]"
+    );
+    println!(
+        "CSV files need a header row. Columns are mapped to fields by their name (e.g. First Name,"
+    );
+    println!(
+        "Surname, Mobile, E-mail Address) unless given with --map; other columns are ignored."
+    );
+    println!(
+        "The delimiter and encoding are detected unless given. Records that can not be imported"
+    );
+    println!("are listed with their line number after the remaining records were imported.");
+    println!(
+        "Google Contacts and Outlook CSV files are read and written with --profile, taking the"
+    );
+    println!("first of their phone, e-mail and address columns:");
+    println!("     import -d phonebook.txt contacts.csv --profile google");
+    println!(
+        "vCard files may hold many contacts; name, phone, e-mail, address, organization, title,"
+    );
+    println!("note, birthday, categories and UID are read, the preferred phone and e-mail if there are several:");
+    println!("     import -d phonebook.txt contacts.vcf --format vcard");
+    println!(
+        "LDIF records are read from inetOrgPerson attributes; further phone numbers and e-mails are"
+    );
+    println!("kept as custom fields. Export names records by cn, adding uid where names repeat:");
+    println!("     export -d phonebook.txt directory.ldif --format ldif --base-dn ou=people,dc=example,dc=com");
+    println!(
+        "abook and mutt alias files are read and written as well. What has no field of its own,"
+    );
+    println!("such as nicknames, aliases and further e-mail addresses, is kept as custom fields:");
+    println!("     import -d phonebook.txt ~/.abook/addressbook --format abook");
+    println!("     export -d phonebook.txt aliases --format mutt");
+    println!("JSON export writes all fields of all entries as an array of objects:");
+    println!("     export -d phonebook.txt --format json");
+}
+
+/// Warns about snapshots that kept their old key when the key was changed.
+fn warn_unreadable_snapshots(path: &str, names: &[String]) {
+    if !names.is_empty() {
+        println!(
+            "Warning: snapshots {} in {} could not be read and keep their old key",
+            names.join(", "),
+            snapshot::dir(path)
+        );
+    }
+}
+
+fn print_help_backup() {
+    println!("Usage of backup and restore commands");
+    println!("     backup -d phonebook.txt [phonebook.bak]");
+    println!("     restore -d phonebook.txt phonebook.bak [--force]");
+    println!("A backup holds all entries with their IDs and timestamps and the settings of the phonebook,");
+    println!("with a checksum that is verified on restore. Restore refuses to overwrite a phonebook that");
+    println!("is not empty unless --force is given, and then takes a snapshot of it if it keeps snapshots.");
+    println!(
+        "The backup of an encrypted phonebook is encrypted with its key and restored encrypted."
+    );
+}
+
+fn print_help_init() {
+    println!("Usage of init command");
+    println!("     init phonebook.txt [--name Family] [-r GB] [--required first,phone] [--unique phone,email]");
+    println!("     init -d phonebook.jsonl");
+    println!(
+        "Creates an empty phonebook with a format header, refusing to overwrite an existing file."
+    );
+    println!(
+        "Its name, region and required and unique fields are stored in the header, where other"
+    );
+    println!(
+        "rules can be added later. Other commands create a missing phonebook when given --create."
+    );
+}
+
+fn print_help_snapshots() {
+    println!("Usage of snapshots command");
+    println!("     snapshots list -d phonebook.txt");
+    println!("     snapshots restore 20261019T101500Z.txt -d phonebook.txt");
+    println!("A phonebook with a #snapshots=N header line copies its file into the directory");
+    println!(
+        "phonebook.txt.snapshots before a command first changes it, keeping the N newest copies."
+    );
+    println!(
+        "With #snapshot_days=D copies older than D days are removed as well. Restoring a snapshot"
+    );
+    println!("takes a snapshot of the current file first, so it can be undone.");
+}
+
+fn print_help_encryption() {
+    println!("Usage of encrypt, change-passphrase and decrypt commands");
+    println!("     encrypt -d phonebook.txt [--key-file phonebook.key]");
+    println!("     change-passphrase -d phonebook.txt [--new-key-file phonebook.key]");
+    println!("     decrypt -d phonebook.txt [plain.txt]");
+    println!(
+        "An encrypted phonebook is locked with a passphrase or a key file of at least 32 bytes."
+    );
+    println!(
+        "Every command opens it with --key-file, the PHONEBOOK_KEY_FILE or PHONEBOOK_PASSPHRASE"
+    );
+    println!(
+        "environment variable, or else asks for the passphrase. New passphrases are asked for or"
+    );
+    println!("taken from PHONEBOOK_PASSPHRASE (encrypt) or PHONEBOOK_NEW_PASSPHRASE (change-passphrase).");
+    println!("decrypt writes the phonebook in plain text to the given file, or else in its place.");
+    println!("Snapshots of the phonebook are encrypted, re-encrypted or decrypted along with it.");
+}
+
+fn print_help_gdpr() {
+    println!("Usage of gdpr command");
+    println!("     gdpr export -i 4 -d phonebook.txt [subject.json]");
+    println!("     gdpr erase -i 4 -d phonebook.txt");
+    println!(
+        "export writes the entry, its copies in snapshots and its audit records as JSON. erase"
+    );
+    println!(
+        "removes the entry from the phonebook and all snapshots without taking a new snapshot,"
+    );
+    println!(
+        "and records only the time and ID in the audit log phonebook.txt.audit. Copies of deleted"
+    );
+    println!("entries that had the same ID are left alone. Backups must be handled by hand.");
+}
+
+fn print_help_query() {
+    println!("Usage of query command");
+    println!("     query -d phonebook.txt john");
+    println!(
+        "The term is a search term as for the search command. Entries with an e-mail address are"
+    );
+    println!(
+        "printed in the format of mutt's query_command: a status line followed by one line of"
+    );
+    println!("e-mail, name and phone number separated by tabs. Exits with 1 if nothing matches. In muttrc:");
+    println!("     set query_command = \"phonebook query -d ~/phonebook.txt %s\"");
+}
+
+fn print_help_merge() {
+    println!("Usage of merge command");
+    println!("     merge 3 7");
+    println!(
+        "Entry 7 is merged into entry 3 and deleted. Values of entry 3 are kept, its empty fields"
+    );
+    println!("are filled from entry 7 and tags of both entries are combined.");
+}
+
+/// Formats entries as the output of mutt's query_command: a status line
+/// followed by e-mail, name and phone of each entry, separated by tabs.
+fn query_lines(entries: &[Entry]) -> Vec<String> {
+    // tabs and line breaks would break the format
+    let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ").trim().to_string();
+    let mut lines = vec![format!("Found {} entries in phonebook", entries.len())];
+    for e in entries.iter() {
+        let name = format!("{} {}", e.first_name, e.last_name);
+        lines.push(format!(
+            "{}\t{}\t{}",
+            clean(&e.e_mail),
+            clean(&name),
+            clean(&e.phone)
+        ));
+    }
+    lines
+}
+
+fn print_query_results(entries: Vec<Entry>) {
+    for line in query_lines(&entries) {
+        println!("{}", line);
+    }
+}
+
+fn print_duplicates(duplicates: Vec<Duplicate>) {
+    if duplicates.is_empty() {
+        println!("No duplicates found");
+        return;
+    }
+    for d in duplicates.iter() {
+        println!(
+            "{:>3}% #{} {} {} <-> #{} {} {} ({})",
+            d.confidence,
+            d.first.id,
+            d.first.first_name,
+            d.first.last_name,
+            d.second.id,
+            d.second.first_name,
+            d.second.last_name,
+            d.reasons.join(", ")
+        );
+    }
+    println!("Use merge <id> <id> to combine duplicates");
 }
 
 fn print_single_entry(entry: &Entry) {
@@ -280,154 +1930,314 @@ fn print_single_entry(entry: &Entry) {
     println!("     Phone: {}", entry.phone);
     println!("   Address: {}", entry.address);
     println!("    E-mail: {}", entry.e_mail);
+    println!("      Tags: {}", entry.tags.join(", "));
+}
+
+/// Prints every field of an entry, including custom fields and timestamps.
+fn print_entry_detail(entry: &Entry) {
+    let time = |t: u64| match t {
+        0 => "unknown".to_string(),
+        _ => DateTime::from_timestamp(t).to_extended(),
+    };
+    let mut lines = vec![
+        ("ID", entry.id.to_string()),
+        ("First name", entry.first_name.clone()),
+        ("Last name", entry.last_name.clone()),
+        ("Phone", entry.phone.clone()),
+        ("Phone E.164", entry.phone_e164.clone()),
+        ("Address", entry.address.clone()),
+        ("E-mail", entry.e_mail.clone()),
+        ("Tags", entry.tags.join(", ")),
+        ("Organization", entry.organization.clone()),
+        ("Title", entry.title.clone()),
+        ("Birthday", entry.birthday.clone()),
+        ("Note", entry.note.replace('\n', "\n              ")),
+        ("UID", entry.uid.clone()),
+    ];
+    for (name, value) in entry.custom.iter() {
+        lines.push((name.as_str(), value.clone()));
+    }
+    lines.push(("Created", time(entry.created_at)));
+    lines.push(("Updated", time(entry.updated_at)));
+    for (label, value) in lines.iter() {
+        println!("{:>12}: {}", label, value);
+    }
+}
+
+fn print_all_entries(entries: Vec<Entry>, format: &Presentation) {
+    match (entries.len(), format.output) {
+        (0, Output::Table) => println!("No entries to show"),
+        (_, Output::Table) => {
+            print_entry_table_separator(false, &format.columns);
+            print_entry_lines_header(false, &format.columns);
+            print_entry_table_separator(false, &format.columns);
+            for e in entries.iter() {
+                print_entry_as_lines(e, None, &format.columns);
+            }
+            print_entry_table_separator(false, &format.columns);
+        }
+        (_, Output::Csv) => print!("{}", csv::write_entries(&entries, &format.columns, ',')),
+        #[cfg(feature = "json")]
+        (_, Output::Json) => match json::write_entries(&entries) {
+            Ok(x) => print!("{}", x),
+            Err(msg) => {
+                println!("Error: {}", msg);
+                std::process::exit(1);
+            }
+        },
+    }
 }
 
-fn print_all_entries(entries: Vec<Entry>) {
-    match entries.len() {
+fn print_ranked_entries(matches: Vec<Match>, show_score: bool, format: &Presentation) {
+    if !show_score || format.output != Output::Table {
+        print_all_entries(matches.into_iter().map(|m| m.entry).collect(), format);
+        return;
+    }
+    match matches.len() {
         0 => println!("No entries to show"),
         _ => {
-            print_entry_table_separator();
-            print_entry_lines_header();
-            print_entry_table_separator();
-            for e in entries.iter() {
-                print_entry_as_lines(e);
+            print_entry_table_separator(true, &format.columns);
+            print_entry_lines_header(true, &format.columns);
+            print_entry_table_separator(true, &format.columns);
+            for m in matches.iter() {
+                print_entry_as_lines(&m.entry, Some(m.score), &format.columns);
             }
-            print_entry_table_separator();
+            print_entry_table_separator(true, &format.columns);
         }
     }
 }
 
-fn print_entry_lines_header() {
-    /*
-    ID: 5
-    FN: 10
-    LN: 15
-    PH: 15
-    AD: 20
-    EM: 20
-    */
-    println!("| ID  |First name|   Last name   |  Phone book   |       Address      |       E-mail       |");
+/// Width of a table column in characters.
+fn column_width(column: Field) -> usize {
+    match column {
+        Field::Id => 5,
+        Field::FirstName | Field::Birthday => 10,
+        Field::LastName | Field::Phone | Field::Tag | Field::Organization | Field::Title => 15,
+        _ => 20,
+    }
 }
 
-fn print_entry_table_separator() {
-    println!("+-----+----------+---------------+---------------+--------------------+--------------------+");
+fn column_label(column: Field) -> &'static str {
+    match column {
+        Field::Id => "ID",
+        Field::Name => "Name",
+        Field::FirstName => "First name",
+        Field::LastName => "Last name",
+        Field::Phone => "Phone book",
+        Field::Address => "Address",
+        Field::Email => "E-mail",
+        Field::Tag => "Tags",
+        Field::Organization => "Organization",
+        Field::Note => "Note",
+        Field::Birthday => "Birthday",
+        Field::Title => "Title",
+        Field::Any => "",
+    }
 }
 
-fn print_entry_as_lines(entry: &Entry) {
+fn print_entry_lines_header(with_score: bool, columns: &[Field]) {
+    if with_score {
+        print!("|Score");
+    }
+    for column in columns.iter() {
+        let width = column_width(*column);
+        print!("|{:^width$}", column_label(*column));
+    }
+    println!("|");
+}
+
+fn print_entry_table_separator(with_score: bool, columns: &[Field]) {
+    if with_score {
+        print!("+-----");
+    }
+    for column in columns.iter() {
+        print!("+{}", "-".repeat(column_width(*column)));
+    }
+    println!("+");
+}
+
+fn print_entry_as_lines(entry: &Entry, score: Option<u32>, columns: &[Field]) {
     let mut has_more: bool;
     let mut i: usize = 0;
     loop {
-        has_more = print_entry_line(entry, i);
+        has_more = print_entry_line(entry, score, columns, i);
         if !has_more {
             break;
         }
-        i = i + 1;
+        i += 1;
     }
 }
 
-fn print_entry_line(entry: &Entry, line_number: usize) -> bool {
-    let mut has_more: bool;
-    let mut temp: bool;
-    print!("|");
-    has_more = print_entry_field(&format!("{}", entry.id), line_number, 5);
-    print!("|");
-    temp = print_entry_field(&entry.first_name, line_number, 10);
-    if temp && !has_more {
-        has_more = true;
-    }
-    print!("|");
-    temp = print_entry_field(&entry.last_name, line_number, 15);
-    if temp && !has_more {
-        has_more = true;
-    }
-    print!("|");
-    temp = print_entry_field(&entry.phone, line_number, 15);
-    if temp && !has_more {
-        has_more = true;
-    }
-    print!("|");
-    temp = print_entry_field(&entry.address, line_number, 20);
-    if temp && !has_more {
-        has_more = true;
-    }
-    print!("|");
-    temp = print_entry_field(&entry.e_mail, line_number, 20);
-    if temp && !has_more {
-        has_more = true;
+fn print_entry_line(
+    entry: &Entry,
+    score: Option<u32>,
+    columns: &[Field],
+    line_number: usize,
+) -> bool {
+    let mut has_more = false;
+    if let Some(score) = score {
+        print!("|");
+        print_entry_field(&format!("{}", score), line_number, 5);
+    }
+    for column in columns.iter() {
+        print!("|");
+        if print_entry_field(&entry.get(*column), line_number, column_width(*column)) {
+            has_more = true;
+        }
     }
     println!("|");
 
     has_more
 }
 
-fn print_entry_field(value: &String, line_number: usize, available_width: usize) -> bool {
+fn print_entry_field(value: &str, line_number: usize, available_width: usize) -> bool {
     if value.len() <= available_width * line_number {
         print!("{:<available_width$}", " ");
-        return false;
+        false
     } else if value.len() > available_width * (line_number + 1) {
-        let sub_str: String = value.clone().chars().skip(available_width * line_number).take(available_width).collect();
+        let sub_str: String = value
+            .chars()
+            .skip(available_width * line_number)
+            .take(available_width)
+            .collect();
         print!("{}", sub_str);
-        return true;
+        true
     } else {
-        let sub_str: String = value.clone().chars().skip(available_width * line_number).take(value.len() - (available_width * line_number)).collect();
+        let sub_str: String = value
+            .chars()
+            .skip(available_width * line_number)
+            .take(value.len() - (available_width * line_number))
+            .collect();
         print!("{:<available_width$}", sub_str);
-        return false;
+        false
     }
 }
 
 fn check_param_id(id: u16) -> Result<(), String> {
-    if id <= 0 {
+    if id == 0 {
         return Err("id must be stated and it must be a positive number".to_string());
     }
     Ok(())
 }
 
-fn check_param_term(term: &String) -> Result<(), String> {
-    if term.eq("") {
+fn check_param_term(term: &str) -> Result<(), String> {
+    if term.is_empty() {
         return Err("term must be stated".to_string());
     }
     Ok(())
 }
 
 fn check_delete_params(p: &Parameters) -> Result<(), String> {
-    check_param_id(p.id.clone())
+    check_param_id(p.id)
+}
+
+fn check_list_params(p: &Parameters) -> Result<(), String> {
+    if p.page > 0 && p.limit == 0 {
+        return Err("page needs a limit of entries per page".to_string());
+    }
+    Ok(())
+}
+
+/// Returns the entries on a page, counted from 1, and the number of pages.
+/// An empty list has one empty page.
+fn page_of(entries: Vec<Entry>, limit: usize, page: usize) -> Result<(Vec<Entry>, usize), String> {
+    let pages = entries.len().div_ceil(limit).max(1);
+    if page > pages {
+        return Err(format!("page {} is beyond the last page {}", page, pages));
+    }
+    let entries = entries
+        .into_iter()
+        .skip((page - 1) * limit)
+        .take(limit)
+        .collect();
+    Ok((entries, pages))
 }
 
 fn check_search_params(p: &Parameters) -> Result<(), String> {
+    if p.show_score && !p.fuzzy {
+        return Err("--show-score needs --fuzzy, only fuzzy search ranks results".to_string());
+    }
     check_param_term(&p.search_term)
 }
 
-fn check_create_params(p: &Parameters) -> Result<(), String> {
-    match true {
-        true if p.first_name.eq("") || p.first_name.eq("--") => return Err("first name must be stated".to_string()),
-        true if p.last_name.eq("") || p.last_name.eq("--")  => return Err("last name must be stated".to_string()),
-        true if p.phone.eq("") || p.phone.eq("--") => return Err("phone number must be stated".to_string()),
-        _ => Ok(()),
+fn check_lookup_params(p: &Parameters) -> Result<(), String> {
+    if !p.search_term.chars().any(|c| c.is_ascii_digit()) {
+        return Err("phone number must be stated".to_string());
+    }
+    Ok(())
+}
+
+fn check_merge_params(p: &Parameters) -> Result<(u16, u16), String> {
+    if p.positional.len() != 2 {
+        return Err("IDs of two entries must be stated".to_string());
+    }
+    let mut ids: Vec<u16> = Vec::new();
+    for s in p.positional.iter() {
+        match s.parse::<u16>() {
+            Ok(x) if x > 0 => ids.push(x),
+            _ => return Err(format!("{} is not a valid ID", s)),
+        }
+    }
+    Ok((ids[0], ids[1]))
+}
+
+fn check_import_params(p: &Parameters) -> Result<(), String> {
+    match p.positional.len() {
+        1 => Ok(()),
+        0 => Err("file to import must be stated".to_string()),
+        _ => Err("only one file can be imported at a time".to_string()),
+    }
+}
+
+fn check_init_params(p: &Parameters) -> Result<(), String> {
+    match p.positional.len() {
+        0 if p.database_path.is_empty() => {
+            Err("path of the new phonebook must be stated".to_string())
+        }
+        0 | 1 => Ok(()),
+        _ => Err("only one phonebook can be created at a time".to_string()),
+    }
+}
+
+fn check_restore_params(p: &Parameters) -> Result<(), String> {
+    if p.database_path.is_empty() {
+        return Err("database path must be stated".to_string());
     }
+    match p.positional.len() {
+        1 => Ok(()),
+        0 => Err("backup file to restore must be stated".to_string()),
+        _ => Err("only one backup can be restored at a time".to_string()),
+    }
+}
+
+fn check_update_params(p: &Parameters) -> Result<(), String> {
+    check_param_id(p.id)
 }
 
 #[cfg(test)]
+#[allow(clippy::single_match)]
 mod tests {
     use super::*;
+    use database::Rules;
 
-    fn generate_create_params() -> Parameters{
-        Parameters{
+    fn generate_create_params() -> Parameters {
+        Parameters {
             id: 1,
             first_name: "John".to_string(),
             last_name: "Smith".to_string(),
-            phone: "123".to_string(),
+            phone: "+1 555 123 4567".to_string(),
             operation: Operation::Create,
             address: "Wild west".to_string(),
             e_mail: "js@gmail.com".to_string(),
             database_path: "/some/path".to_string(),
-            search_term: "".to_string()
+            ..Default::default()
         }
     }
 
     #[test]
     fn check_good_delete_params() {
         let p = generate_create_params();
-        match check_create_params(&p) {
+        match Rules::default().validate(&p.into_entry(), None) {
             Err(_msg) => panic!("This should be successful!"),
             Ok(()) => {}
         }
@@ -437,9 +2247,147 @@ mod tests {
     fn fail_create_params_missing_first_name() {
         let mut p = generate_create_params();
         p.first_name = String::from("");
-        match check_create_params(&p) {
+        match Rules::default().validate(&p.into_entry(), None) {
             Ok(()) => panic!("This should fail!"),
-            Err(msg) => assert_eq!("first name must be stated".to_string(), msg)
+            Err(v) => assert_eq!("first name must be stated".to_string(), v.to_string()),
         }
     }
+
+    #[test]
+    fn show_score_needs_fuzzy() {
+        let mut p = Parameters {
+            search_term: "smiht".to_string(),
+            show_score: true,
+            ..Default::default()
+        };
+        assert!(check_search_params(&p).is_err());
+        p.fuzzy = true;
+        assert_eq!(Ok(()), check_search_params(&p));
+    }
+
+    #[test]
+    fn import_applies_duplicate_policy_after_validation() {
+        let path =
+            std::env::temp_dir().join(format!("phonebook-import-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let mut db = FileDB::init(path.clone(), &Metadata::default()).unwrap();
+        db.create(generate_create_params().into_entry()).unwrap();
+        let duplicate = generate_create_params().into_entry();
+        let mut invalid = generate_create_params().into_entry();
+        invalid.first_name = String::new();
+
+        let mut errors = Vec::new();
+        let rows = vec![(2, duplicate.clone()), (3, invalid.clone())];
+        assert_eq!(
+            Ok(0),
+            import_entries(&mut db, rows, &mut errors, OnDuplicate::Skip)
+        );
+        assert_eq!(
+            vec![3],
+            errors.iter().map(|e| e.line).collect::<Vec<usize>>()
+        );
+        assert!(errors[0].message.starts_with("invalid entry"));
+
+        let mut errors = Vec::new();
+        let rows = vec![(2, duplicate), (3, invalid)];
+        assert_eq!(
+            Ok(0),
+            import_entries(&mut db, rows, &mut errors, OnDuplicate::Fail)
+        );
+        assert_eq!(
+            vec![
+                "John Smith has the phone number or e-mail of #1 John Smith",
+                "invalid entry: first name must be stated",
+            ],
+            errors
+                .iter()
+                .map(|e| e.message.as_str())
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!(1, db.read_all().len());
+
+        let mut mary = generate_create_params().into_entry();
+        mary.first_name = "Mary".to_string();
+        mary.phone = "+1 555 765 4321".to_string();
+        mary.e_mail = "mary@gmail.com".to_string();
+        let mut errors = Vec::new();
+        let rows = vec![(2, mary)];
+        assert_eq!(
+            Ok(1),
+            import_entries(&mut db, rows, &mut errors, OnDuplicate::Fail)
+        );
+        assert_eq!(2, FileDB::new(path.clone()).unwrap().read_all().len());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn format_query_results() {
+        let entries = vec![
+            Entry {
+                first_name: "John".to_string(),
+                last_name: "Smith\tJr.".to_string(),
+                phone: "+1 555 123 4567".to_string(),
+                e_mail: " js@gmail.com\n".to_string(),
+                ..Default::default()
+            },
+            Entry {
+                first_name: "Mary".to_string(),
+                e_mail: "mary@gmail.com".to_string(),
+                phone: "555\r\n1234".to_string(),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            vec![
+                "Found 2 entries in phonebook",
+                "js@gmail.com\tJohn Smith Jr.\t+1 555 123 4567",
+                "mary@gmail.com\tMary\t555  1234",
+            ],
+            query_lines(&entries)
+        );
+    }
+
+    #[test]
+    fn page_through_entries() {
+        let entries: Vec<Entry> = (1..=5)
+            .map(|id| Entry {
+                id,
+                ..Default::default()
+            })
+            .collect();
+        let (page, pages) = page_of(entries.clone(), 2, 3).unwrap();
+        assert_eq!(vec![5], page.iter().map(|e| e.id).collect::<Vec<u16>>());
+        assert_eq!(3, pages);
+        assert_eq!(
+            Err("page 4 is beyond the last page 3".to_string()),
+            page_of(entries, 2, 4).map(|_| ())
+        );
+        assert_eq!(1, page_of(Vec::new(), 2, 1).unwrap().1);
+    }
+
+    #[test]
+    fn sort_listed_entries() {
+        let entry = |id: u16, last_name: &str, updated_at: u64| Entry {
+            id,
+            last_name: last_name.to_string(),
+            updated_at,
+            ..Default::default()
+        };
+        let mut entries = vec![
+            entry(1, "smith", 30),
+            entry(2, "Adams", 10),
+            entry(3, "Smith", 20),
+        ];
+        sort_entries(&mut entries, &parse_sort("last,-updated").unwrap());
+        assert_eq!(
+            vec![2, 1, 3],
+            entries.iter().map(|e| e.id).collect::<Vec<u16>>()
+        );
+        sort_entries(&mut entries, &parse_sort("-last").unwrap());
+        assert_eq!(
+            vec![1, 3, 2],
+            entries.iter().map(|e| e.id).collect::<Vec<u16>>()
+        );
+        assert!(parse_sort("any").is_err());
+    }
 }
@@ -1,6 +1,4 @@
-mod lib;
-
-use lib::execute;
+use phonebook::execute;
 use std::env;
 
 fn main() {